@@ -1,30 +1,109 @@
+use crate::loading::Progress;
+use crate::snapshot;
+use flate2::read::MultiGzDecoder;
 use lb_rs::model::file::File;
+use lb_rs::model::file_metadata::FileType;
 use lb_rs::Uuid;
-use serde::Deserialize;
-use std::{collections::HashMap, fs};
+use ruzstd::decoding::StreamingDecoder;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    fs::File as FsFile,
+    io::{self, BufReader, Cursor, Read},
+};
+
+//How compressed snapshots start, so they're recognised whatever the file is called
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+//The first `len` bytes, or fewer only if the input is that short. Decoders and pipes can hand
+//bytes over a few at a time, so a single read isn't enough to tell formats apart
+fn read_start(reader: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut start = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut start)?;
+    Ok(start)
+}
+
+//Flipped into a folder's id to get a stable id for its group of small items
+const GROUP_MASK: u128 = 0x6f74_6865_7273_0000_0000_0000_0000_0000;
+
+//How siblings are ordered, ties always fall back to size and then name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortMode {
+    Size,
+    Name,
+    LastModified,
+    Type,
+}
+
+impl SortMode {
+    pub const ALL: [SortMode; 4] = [
+        SortMode::Size,
+        SortMode::Name,
+        SortMode::LastModified,
+        SortMode::Type,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortMode::Size => "Size",
+            SortMode::Name => "Name",
+            SortMode::LastModified => "Last modified",
+            SortMode::Type => "Type",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Data {
     pub current_root: Uuid,
     pub all_files: HashMap<Uuid, FileRow>,
     pub folder_sizes: HashMap<Uuid, u64>,
+    //Ids directly inside each folder, kept in step with all_files
+    pub children: HashMap<Uuid, HashSet<Uuid>>,
     pub overall_root: Uuid,
+    //Siblings smaller than this many bytes are drawn as one group, 0 turns grouping off
+    pub group_below: u64,
+    //Folders whose small items have been clicked open
+    pub expanded_groups: HashSet<Uuid>,
+    //Deepest layer get_children will build, anything below is left for when the user zooms in
+    pub max_depth: Option<u64>,
+    //How siblings are ordered from left to right
+    pub sort: SortMode,
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Node {
     pub id: Uuid,
     pub name: String,
-    pub portion: f32,
+    pub size: u64,
     pub children: Vec<Node>,
+    //Ids of the small siblings this node stands in for, empty for ordinary files and folders
+    pub grouped: Vec<Uuid>,
+    //Set when this folder has contents that were cut off by `max_depth`
+    pub hidden_children: bool,
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct NodeLayer {
     pub id: Uuid,
     pub name: String,
-    pub portion: f32,
+    pub size: u64,
+    //Bytes painted to the left of this slice on its layer, counted from the root's left edge
+    pub offset: u64,
     pub layer: u64,
+    pub grouped: Vec<Uuid>,
+    pub hidden_children: bool,
+}
+
+impl NodeLayer {
+    //The real file to ask about parents and ancestry, since groups have no entry of their own
+    pub fn representative(&self) -> Uuid {
+        self.grouped.first().copied().unwrap_or(self.id)
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Hash, PartialEq, Eq)]
@@ -33,35 +112,163 @@ pub struct FileRow {
     pub size: u64,
 }
 
+//One line of a size report, using the same numbers the chart is drawn from
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct ReportRow {
+    pub id: Uuid,
+    pub path: String,
+    pub file_type: String,
+    pub own_size: u64,
+    pub aggregated_size: u64,
+    pub percentage: f64,
+    pub depth: u64,
+    pub last_modified: u64,
+    pub last_modified_by: String,
+}
+
+//Folds the rows of a JSON array into `Data` as they are parsed, so the whole list is never held twice.
+//Counts them and stops if loading is cancelled
+struct RowVisitor<'a> {
+    progress: &'a Progress,
+}
+
+impl<'de> Visitor<'de> for RowVisitor<'_> {
+    type Value = Data;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of files")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut data = Data::empty();
+        while let Some(row) = seq.next_element()? {
+            if self.progress.is_cancelled() {
+                return Err(de::Error::custom("cancelled"));
+            }
+            self.progress.parsed();
+            data.add_row(row);
+        }
+        Ok(data)
+    }
+}
+
 impl Data {
-    pub fn from_file(file: String) -> Vec<FileRow> {
-        let file_contents = fs::read_to_string(file).expect("Couldn't read file");
-        return serde_json::from_str(&file_contents).expect("Json not formatted well");
+    //Reads a binary snapshot or a JSON one, either of them optionally gzip or zstd compressed.
+    //They're told apart by their first bytes and decompressed while being parsed
+    pub fn from_file(file: &str, progress: &Progress) -> Result<Self, String> {
+        let couldnt_read = |err: &dyn fmt::Display| format!("Couldn't read {}: {}", file, err);
+        let mut reader = BufReader::new(FsFile::open(file).map_err(|err| couldnt_read(&err))?);
+        let start = read_start(&mut reader, ZSTD_MAGIC.len()).map_err(|err| couldnt_read(&err))?;
+        let reader = Cursor::new(start.clone()).chain(reader);
+        let mut reader: Box<dyn Read> = if start.starts_with(GZIP_MAGIC) {
+            Box::new(MultiGzDecoder::new(reader))
+        } else if start.starts_with(ZSTD_MAGIC) {
+            Box::new(StreamingDecoder::new(reader).map_err(|err| couldnt_read(&err))?)
+        } else {
+            Box::new(reader)
+        };
+        let start =
+            read_start(&mut reader, snapshot::MAGIC.len()).map_err(|err| couldnt_read(&err))?;
+        if start == snapshot::MAGIC {
+            Data::from_snapshot(BufReader::new(reader), progress)
+        } else {
+            Data::from_reader(BufReader::new(Cursor::new(start).chain(reader)), progress)
+        }
+    }
+
+    //Builds from a binary snapshot whose magic bytes were already read
+    pub fn from_snapshot(reader: impl Read, progress: &Progress) -> Result<Self, String> {
+        let mut data = Data::empty();
+        snapshot::read_rows(reader, progress, |row| data.add_row(row))?;
+        data.aggregate(progress)
+            .ok_or("Loading was cancelled".to_owned())
+    }
+
+    //Every row, ordered by id so the same listing always writes the same snapshot
+    pub fn rows(&self) -> Vec<FileRow> {
+        let mut rows: Vec<FileRow> = self.all_files.values().cloned().collect();
+        rows.sort_by_key(|row| row.file.id);
+        rows
+    }
+
+    //Parses a JSON snapshot straight from `reader` without loading all of it into memory first
+    pub fn from_reader(reader: impl Read, progress: &Progress) -> Result<Self, String> {
+        serde_json::Deserializer::from_reader(reader)
+            .deserialize_seq(RowVisitor { progress })
+            .map_err(|err| match progress.is_cancelled() {
+                true => "Loading was cancelled".to_owned(),
+                false => format!("Json not formatted well: {}", err),
+            })?
+            .aggregate(progress)
+            .ok_or("Loading was cancelled".to_owned())
     }
 
     pub fn init(data: Vec<FileRow>) -> Self {
-        let mut all_files = HashMap::new();
-        let mut root = Uuid::nil();
-        for datum in data.clone() {
-            if datum.file.id == datum.file.parent {
-                root = datum.file.id;
-            }
-            all_files.insert(datum.file.id, datum);
+        Data::init_with_progress(data, &Progress::default()).unwrap()
+    }
+
+    //Same as init, but counts rows as their sizes are added up and gives up if loading is cancelled
+    pub fn init_with_progress(data: Vec<FileRow>, progress: &Progress) -> Option<Self> {
+        let mut result = Data::empty();
+        for datum in data {
+            result.add_row(datum);
+        }
+        result.aggregate(progress)
+    }
+
+    fn empty() -> Self {
+        Self {
+            current_root: Uuid::nil(),
+            overall_root: Uuid::nil(),
+            all_files: HashMap::new(),
+            folder_sizes: HashMap::new(),
+            children: HashMap::new(),
+            group_below: 0,
+            expanded_groups: HashSet::new(),
+            max_depth: None,
+            sort: SortMode::Size,
         }
+    }
+
+    //Files a row under its parent; folder sizes are left for `aggregate` once every row is in
+    fn add_row(&mut self, datum: FileRow) {
+        if datum.file.id == datum.file.parent {
+            self.current_root = datum.file.id;
+            self.overall_root = datum.file.id;
+        } else {
+            self.children
+                .entry(datum.file.parent)
+                .or_default()
+                .insert(datum.file.id);
+        }
+        self.all_files.insert(datum.file.id, datum);
+    }
 
-        let mut folder_sizes = HashMap::new();
+    //Adds every file's size to all of its ancestors
+    fn aggregate(mut self, progress: &Progress) -> Option<Self> {
+        let root = self.overall_root;
+        progress
+            .rows_total
+            .store(self.all_files.len(), std::sync::atomic::Ordering::Relaxed);
         //Initial for loop for folders is necessary to give folders starting value as we need to go over folders again to update sizes
-        for datum in data.clone() {
+        for datum in self.all_files.values() {
             if datum.file.is_folder() {
-                folder_sizes.insert(datum.file.id, datum.size); //change to datum.size when metadata is accounted for
+                self.folder_sizes.insert(datum.file.id, datum.size); //change to datum.size when metadata is accounted for
             }
         }
-        for datum in data {
+        for datum in self.all_files.values() {
+            if progress.is_cancelled() {
+                return None;
+            }
+            progress
+                .rows_aggregated
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
             let datum_size = datum.size;
             let mut current_id = datum.file.id;
-            loop {
-                let row = all_files.get(&current_id).unwrap();
-                let mut current_size = folder_sizes
+            //A row cut off from the root only counts towards the ancestors that are there
+            while let Some(row) = self.all_files.get(&current_id) {
+                let mut current_size = self
+                    .folder_sizes
                     .get(&row.file.parent)
                     .copied()
                     .unwrap_or_default();
@@ -69,98 +276,487 @@ impl Data {
                 if current_id == root {
                     break;
                 }
-                folder_sizes.insert(row.file.parent, current_size);
+                self.folder_sizes.insert(row.file.parent, current_size);
                 current_id = row.file.parent;
             }
         }
+        Some(self)
+    }
 
-        Self {
-            current_root: root,
-            overall_root: root,
-            all_files,
-            folder_sizes,
-        }
+    //The rows directly inside a folder, in no particular order
+    fn children_of(&self, id: &Uuid) -> impl Iterator<Item = &FileRow> {
+        self.children
+            .get(id)
+            .into_iter()
+            .flatten()
+            .map(|child| self.all_files.get(child).unwrap())
+    }
+
+    pub fn group_id(parent: &Uuid) -> Uuid {
+        Uuid::from_u128(parent.as_u128() ^ GROUP_MASK)
     }
 
     pub fn get_children(&self, id: &Uuid) -> Vec<Node> {
+        self.get_children_at(id, 1)
+    }
+
+    //A folder holds more than its own metadata only when something is stored beneath it
+    fn has_contents(&self, id: &Uuid) -> bool {
+        let row = self.all_files.get(id).unwrap();
+        row.file.is_folder() && self.get_size(id) > row.size
+    }
+
+    //Builds the children that will be drawn on `layer`, stopping once `max_depth` is reached
+    fn get_children_at(&self, id: &Uuid, layer: u64) -> Vec<Node> {
         if !self.all_files.get(id).unwrap().file.is_folder() {
             return vec![];
         }
-        let total_size = *self.folder_sizes.get(&self.current_root).unwrap() as f32;
-        let children = self
-            .all_files
-            .values()
-            .filter(|f| f.file.parent == *id && f.file.id != *id)
-            .map(|f| {
-                let mut current_size = f.size as f32;
-                if f.file.is_folder() {
-                    current_size = *self.folder_sizes.get(&f.file.id).unwrap() as f32;
-                }
-                Node {
-                    id: f.file.id,
-                    name: f.file.name.clone(),
-                    portion: current_size / total_size,
-                    children: self.get_children(&f.file.id),
-                }
+        let is_last_layer = self.max_depth.is_some_and(|max_depth| layer >= max_depth);
+        let mut gathered_children: Vec<(&FileRow, u64)> = self
+            .children_of(id)
+            .map(|f| (f, self.get_size(&f.file.id)))
+            .collect();
+        gathered_children.sort_by(|a, b| self.compare_siblings(*a, *b));
+
+        let (mut kept, mut small): (Vec<_>, Vec<_>) =
+            if self.group_below > 0 && !self.expanded_groups.contains(id) {
+                gathered_children
+                    .into_iter()
+                    .partition(|(_, size)| *size >= self.group_below)
+            } else {
+                (gathered_children, vec![])
+            };
+
+        //A lone small item isn't worth a group of its own
+        if small.len() == 1 {
+            kept.append(&mut small);
+        }
+
+        let mut children: Vec<Node> = kept
+            .iter()
+            .map(|(f, size)| Node {
+                id: f.file.id,
+                name: f.file.name.clone(),
+                size: *size,
+                children: if is_last_layer {
+                    vec![]
+                } else {
+                    self.get_children_at(&f.file.id, layer + 1)
+                },
+                grouped: vec![],
+                hidden_children: is_last_layer && self.has_contents(&f.file.id),
+            })
+            .collect();
+        if !small.is_empty() {
+            let small_size: u64 = small.iter().map(|(_, size)| size).sum();
+            children.push(Node {
+                id: Data::group_id(id),
+                name: format!("{} small items", small.len()),
+                size: small_size,
+                children: vec![],
+                grouped: small.iter().map(|(f, _)| f.file.id).collect(),
+                hidden_children: false,
             });
-        let mut gathered_children = vec![];
-        for child in children.into_iter() {
-            gathered_children.push(child);
-        }
-        gathered_children.sort_by(|a, b| {
-            let a_size = (a.portion * 10000.0) as u32;
-            let b_size = (b.portion * 10000.0) as u32;
-            b_size.cmp(&a_size)
-        });
-        return gathered_children;
-    }
-
-    fn set_layers(
-        tree: &Vec<Node>,
-        current_layer: u64,
-        mut raw_layers: Vec<NodeLayer>,
-    ) -> Vec<NodeLayer> {
+        }
+        children
+    }
+
+    //Biggest first, with ties settled by name and then id so the order never depends on hashing
+    fn largest_first(a: (&FileRow, u64), b: (&FileRow, u64)) -> Ordering {
+        b.1.cmp(&a.1)
+            .then_with(|| a.0.file.name.cmp(&b.0.file.name))
+            .then_with(|| a.0.file.id.cmp(&b.0.file.id))
+    }
+
+    //Orders siblings by the chosen sort mode, always ending on largest_first so no two compare equal
+    fn compare_siblings(&self, a: (&FileRow, u64), b: (&FileRow, u64)) -> Ordering {
+        let first = match self.sort {
+            SortMode::Size => Ordering::Equal,
+            SortMode::Name => {
+                a.0.file
+                    .name
+                    .to_lowercase()
+                    .cmp(&b.0.file.name.to_lowercase())
+            }
+            SortMode::LastModified => b.0.file.last_modified.cmp(&a.0.file.last_modified),
+            SortMode::Type => Data::type_rank(a.0).cmp(&Data::type_rank(b.0)),
+        };
+        first.then_with(|| Data::largest_first(a, b))
+    }
+
+    //Folders, then documents, then links
+    fn type_rank(row: &FileRow) -> u8 {
+        match row.file.file_type {
+            FileType::Folder => 0,
+            FileType::Document => 1,
+            FileType::Link { .. } => 2,
+        }
+    }
+
+    //Walks the tree breadth first so the paint order comes out layer by layer, each slice
+    //starting where its parent starts plus the width of the siblings painted before it
+    fn set_layers(tree: Vec<Node>) -> Vec<NodeLayer> {
+        let mut paint_order = vec![];
+        let mut queue = VecDeque::new();
+        let mut offset = 0;
         for slice in tree {
-            raw_layers.push(NodeLayer {
+            let size = slice.size;
+            queue.push_back((slice, 1, offset));
+            offset += size;
+        }
+        while let Some((slice, layer, offset)) = queue.pop_front() {
+            let mut child_offset = offset;
+            for child in slice.children {
+                let size = child.size;
+                queue.push_back((child, layer + 1, child_offset));
+                child_offset += size;
+            }
+            paint_order.push(NodeLayer {
                 id: slice.id,
-                name: slice.name.clone(),
-                portion: slice.portion,
-                layer: current_layer,
+                name: slice.name,
+                size: slice.size,
+                offset,
+                layer,
+                grouped: slice.grouped,
+                hidden_children: slice.hidden_children,
             });
-            if !slice.children.is_empty() {
-                let hold = Data::set_layers(&slice.children, current_layer + 1, raw_layers.clone());
-                for item in hold {
-                    if raw_layers.contains(&item) {
-                        continue;
-                    }
-                    raw_layers.push(item.clone());
+        }
+        paint_order
+    }
+
+    //Whether `id` is `ancestor` or sits somewhere beneath it
+    pub fn is_within(&self, id: &Uuid, ancestor: &Uuid) -> bool {
+        let mut current_id = *id;
+        loop {
+            if current_id == *ancestor {
+                return true;
+            }
+            let Some(row) = self.all_files.get(&current_id) else {
+                return false;
+            };
+            if row.file.parent == current_id {
+                return false;
+            }
+            current_id = row.file.parent;
+        }
+    }
+
+    //Drops anything already covered by another picked folder, so nothing is counted twice
+    pub fn topmost(&self, ids: &[Uuid]) -> Vec<Uuid> {
+        ids.iter()
+            .filter(|id| {
+                !ids.iter()
+                    .any(|other| other != *id && self.is_within(id, other))
+            })
+            .copied()
+            .collect()
+    }
+
+    //Applies `change` to the size of `folder` and of every folder above it
+    fn update_ancestors(&mut self, folder: Uuid, change: impl Fn(u64) -> u64) {
+        let mut current_id = folder;
+        loop {
+            if let Some(size) = self.folder_sizes.get_mut(&current_id) {
+                *size = change(*size);
+            }
+            let parent = self.all_files.get(&current_id).unwrap().file.parent;
+            if parent == current_id {
+                break;
+            }
+            current_id = parent;
+        }
+    }
+
+    //Adds a file to an existing folder, growing the folders above it to match. Hands back false and
+    //changes nothing when the id is already there, the root included, or the folder isn't
+    pub fn insert(&mut self, row: FileRow) -> bool {
+        let id = row.file.id;
+        let parent = row.file.parent;
+        if self.all_files.contains_key(&id)
+            || !self
+                .all_files
+                .get(&parent)
+                .is_some_and(|parent| parent.file.is_folder())
+        {
+            return false;
+        }
+        if row.file.is_folder() {
+            self.folder_sizes.insert(id, 0);
+        }
+        let size = row.size;
+        let start = if row.file.is_folder() { id } else { parent };
+        self.all_files.insert(id, row);
+        self.children.entry(parent).or_default().insert(id);
+        self.update_ancestors(start, |folder_size| folder_size + size);
+        true
+    }
+
+    //Takes a file and everything under it out, shrinking the folders above it to match
+    pub fn remove(&mut self, id: &Uuid) {
+        let size = self.get_size(id);
+        let parent = self.all_files.get(id).unwrap().file.parent;
+        self.update_ancestors(parent, |folder_size| folder_size - size);
+        if let Some(siblings) = self.children.get_mut(&parent) {
+            siblings.remove(id);
+        }
+        let mut removed = vec![*id];
+        while let Some(file) = removed.pop() {
+            removed.extend(self.children.remove(&file).into_iter().flatten());
+            self.all_files.remove(&file);
+            self.folder_sizes.remove(&file);
+            self.expanded_groups.remove(&file);
+        }
+    }
+
+    //Changes a file's own size, leaving whatever is stored beneath it alone
+    pub fn resize(&mut self, id: &Uuid, new_size: u64) {
+        let row = self.all_files.get_mut(id).unwrap();
+        let old_size = std::mem::replace(&mut row.size, new_size);
+        let start = if row.file.is_folder() {
+            *id
+        } else {
+            row.file.parent
+        };
+        self.update_ancestors(start, |folder_size| folder_size - old_size + new_size);
+    }
+
+    pub fn rename(&mut self, id: &Uuid, name: &str) {
+        self.all_files.get_mut(id).unwrap().file.name = name.to_owned();
+    }
+
+    //Brings everything in line with a fresh listing of the same account, handing back what changed
+    pub fn update(&mut self, rows: Vec<FileRow>) -> HashSet<Uuid> {
+        let mut rows: HashMap<Uuid, FileRow> =
+            rows.into_iter().map(|row| (row.file.id, row)).collect();
+        //None for rows that don't reach the root through the listing
+        let mut depths: HashMap<Uuid, Option<u64>> = HashMap::new();
+        for id in rows.keys() {
+            let mut chain = vec![];
+            let mut current_id = *id;
+            let mut depth = loop {
+                if let Some(depth) = depths.get(&current_id) {
+                    break *depth;
                 }
+                let Some(row) = rows.get(&current_id) else {
+                    break None;
+                };
+                if row.file.parent == current_id {
+                    depths.insert(current_id, Some(0));
+                    break Some(0);
+                }
+                chain.push(current_id);
+                current_id = row.file.parent;
+            };
+            for link in chain.into_iter().rev() {
+                depth = depth.map(|depth| depth + 1);
+                depths.insert(link, depth);
             }
         }
-        return raw_layers;
+        //A partial sync can list a file before its folder. It's left out like anything unlisted,
+        //and picked up by a later listing that has the folder too
+        rows.retain(|id, _| depths[id].is_some());
+        let depths: HashMap<Uuid, u64> = depths
+            .into_iter()
+            .filter_map(|(id, depth)| Some((id, depth?)))
+            .collect();
+
+        //Parents go first so every file lands in a folder that's already where it belongs
+        let mut ordered: Vec<&FileRow> = rows
+            .values()
+            .filter(|row| depths[&row.file.id] > 0)
+            .collect();
+        ordered.sort_by_key(|row| depths[&row.file.id]);
+        let mut changed = HashSet::new();
+        for row in ordered {
+            let id = row.file.id;
+            let Some(old) = self.all_files.get(&id) else {
+                self.insert(row.clone());
+                changed.insert(id);
+                continue;
+            };
+            let (old_parent, old_name, old_size) =
+                (old.file.parent, old.file.name.clone(), old.size);
+            if old_parent != row.file.parent {
+                self.reparent(&id, &row.file.parent);
+                changed.insert(id);
+            }
+            if old_name != row.file.name {
+                self.rename(&id, &row.file.name);
+                changed.insert(id);
+            }
+            if old_size != row.size {
+                self.resize(&id, row.size);
+                changed.insert(id);
+            }
+            self.all_files.get_mut(&id).unwrap().file = row.file.clone();
+        }
+
+        let gone: Vec<Uuid> = self
+            .all_files
+            .keys()
+            .filter(|id| !rows.contains_key(id))
+            .copied()
+            .collect();
+        for id in gone {
+            if self.all_files.contains_key(&id) {
+                self.remove(&id);
+            }
+        }
+        if !self.all_files.contains_key(&self.current_root) {
+            self.current_root = self.overall_root;
+        }
+        changed
     }
 
-    pub fn get_paint_order(&self) -> Vec<NodeLayer> {
-        //maybe add paint order to a field of self so that it only calls set if nothing is present/current root is changed
+    //Moves a file under another folder, shifting its size from the old ancestors to the new ones
+    pub fn reparent(&mut self, id: &Uuid, new_parent: &Uuid) {
+        let size = self.get_size(id);
+        let old_parent = self.all_files.get(id).unwrap().file.parent;
+        self.update_ancestors(old_parent, |folder_size| folder_size - size);
+        self.update_ancestors(*new_parent, |folder_size| folder_size + size);
+        self.all_files.get_mut(id).unwrap().file.parent = *new_parent;
+        if let Some(siblings) = self.children.get_mut(&old_parent) {
+            siblings.remove(id);
+        }
+        self.children.entry(*new_parent).or_default().insert(*id);
+    }
+
+    //Whether `id` can be dropped into `folder` without ending up inside itself
+    pub fn can_move(&self, id: &Uuid, folder: &Uuid) -> bool {
+        self.all_files
+            .get(folder)
+            .is_some_and(|row| row.file.is_folder())
+            && self.all_files.get(id).unwrap().file.parent != *folder
+            && !self.is_within(folder, id)
+    }
+
+    pub fn get_size(&self, id: &Uuid) -> u64 {
+        let row = self.all_files.get(id).unwrap();
+        if row.file.is_folder() {
+            *self.folder_sizes.get(id).unwrap()
+        } else {
+            row.size
+        }
+    }
+
+    //Slash separated names from the overall root down to the file
+    pub fn get_path(&self, id: &Uuid) -> String {
+        let mut names = vec![];
+        let mut current_id = *id;
+        loop {
+            let row = self.all_files.get(&current_id).unwrap();
+            names.push(row.file.name.clone());
+            if row.file.parent == current_id {
+                break;
+            }
+            current_id = row.file.parent;
+        }
+        names.reverse();
+        names.join("/")
+    }
+
+    //The ancestor sitting directly under the overall root, and how many levels below the overall root `id` is
+    pub fn get_top_level(&self, id: &Uuid) -> Option<(Uuid, u64)> {
+        let mut current_id = *id;
+        let mut depth = 0;
+        loop {
+            let row = self.all_files.get(&current_id)?;
+            if row.file.parent == current_id {
+                return None;
+            }
+            if row.file.parent == self.overall_root {
+                return Some((current_id, depth + 1));
+            }
+            current_id = row.file.parent;
+            depth += 1;
+        }
+    }
+
+    //Files whose names contain `query`, ignoring case, in path order
+    pub fn find(&self, query: &str) -> Vec<Uuid> {
+        if query.is_empty() {
+            return vec![];
+        }
+        let query = query.to_lowercase();
+        let mut matches: Vec<(String, Uuid)> = self
+            .all_files
+            .values()
+            .filter(|f| f.file.id != self.overall_root)
+            .filter(|f| f.file.name.to_lowercase().contains(&query))
+            .map(|f| (self.get_path(&f.file.id), f.file.id))
+            .collect();
+        matches.sort();
+        matches.into_iter().map(|(_, id)| id).collect()
+    }
+
+    //The folder at a slash separated path like the ones get_path gives
+    pub fn find_folder(&self, path: &str) -> Option<Uuid> {
+        self.all_files
+            .values()
+            .filter(|f| f.file.is_folder())
+            .map(|f| f.file.id)
+            .find(|id| self.get_path(id) == path.trim_matches('/'))
+    }
 
-        let tree = self.get_children(&self.current_root);
-        let mut paint_order_vec = Data::set_layers(&tree, 1, vec![]);
-        paint_order_vec.sort_by(|a, b| a.layer.cmp(&b.layer));
-        return paint_order_vec;
+    //Children of the overall root from largest to smallest, ties broken by name then id
+    pub fn get_top_level_order(&self) -> Vec<Uuid> {
+        let mut top_level: Vec<(&FileRow, u64)> = self
+            .children_of(&self.overall_root)
+            .map(|f| (f, self.get_size(&f.file.id)))
+            .collect();
+        top_level.sort_by(|a, b| Data::largest_first(*a, *b));
+        top_level.iter().map(|(f, _)| f.file.id).collect()
+    }
+
+    //Every file under the current root, sorted by path
+    pub fn get_report(&self) -> Vec<ReportRow> {
+        let total_size = self.get_size(&self.current_root).max(1) as f64;
+        let mut report = vec![];
+        let mut stack = vec![(self.current_root, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let row = self.all_files.get(&id).unwrap();
+            let aggregated_size = self.get_size(&id);
+            report.push(ReportRow {
+                id,
+                path: self.get_path(&id),
+                file_type: match row.file.file_type {
+                    FileType::Document => "Document",
+                    FileType::Folder => "Folder",
+                    FileType::Link { .. } => "Link",
+                }
+                .to_owned(),
+                own_size: row.size,
+                aggregated_size,
+                percentage: aggregated_size as f64 / total_size * 100.0,
+                depth,
+                last_modified: row.file.last_modified,
+                last_modified_by: row.file.last_modified_by.clone(),
+            });
+            if row.file.is_folder() {
+                stack.extend(self.children_of(&id).map(|f| (f.file.id, depth + 1)));
+            }
+        }
+        report.sort_by(|a, b| a.path.cmp(&b.path));
+        report
+    }
+
+    pub fn get_paint_order(&self) -> Vec<NodeLayer> {
+        Data::set_layers(self.get_children(&self.current_root))
     }
 }
 
-//Some of these tests may not work due to change in logic - will be fixed soon
 #[cfg(test)]
 mod test {
     use super::Data;
+    use crate::data::SortMode;
     use crate::data::{FileRow, Node, NodeLayer};
+    use crate::loading::Progress;
     use lb_rs::model::file::File;
     use lb_rs::model::file_metadata::FileType;
     use lb_rs::Uuid;
 
     fn get_root_two_files() -> Vec<FileRow> {
-        return vec![
+        vec![
             FileRow {
                 file: File {
                     id: Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap(),
@@ -197,43 +793,12 @@ mod test {
                 },
                 size: 600,
             },
-        ];
+        ]
     }
 
-    #[test]
-    fn init_root_checker() {
-        let hold = Data::init(get_root_two_files());
-        let expected_root = Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap();
-        assert_eq!(hold.current_root, expected_root);
-        let root_size = (hold.folder_sizes.get(&expected_root)).unwrap().clone();
-        assert_eq!(root_size, 2400);
-    }
-
-    //this test sometimes outputs in different orders
-    #[test]
-    fn get_children_root_two_files() {
-        let hold = Data::init(get_root_two_files());
-        let actual_children = Data::get_children(&hold, &hold.current_root);
-        let expected_children = vec![
-            Node {
-                id: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
-                name: "file1".to_string(),
-                portion: 800.0 / 2400.0,
-                children: vec![],
-            },
-            Node {
-                id: Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap(),
-                name: "file2".to_string(),
-                portion: 600.0 / 2400.0,
-                children: vec![],
-            },
-        ];
-        assert_eq!(expected_children, actual_children);
-    }
-
-    #[test]
-    fn get_children_nested_folders() {
-        let data: Vec<FileRow> = vec![
+    //A root holding a folder, a folder inside that, and a file at the bottom
+    fn get_root_nested_folders() -> Vec<FileRow> {
+        vec![
             FileRow {
                 file: File {
                     id: Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap(),
@@ -282,24 +847,68 @@ mod test {
                 },
                 size: 800,
             },
+        ]
+    }
+
+    #[test]
+    fn init_root_checker() {
+        let hold = Data::init(get_root_two_files());
+        let expected_root = Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap();
+        assert_eq!(hold.current_root, expected_root);
+        let root_size = *hold.folder_sizes.get(&expected_root).unwrap();
+        assert_eq!(root_size, 2400);
+    }
+
+    #[test]
+    fn get_children_root_two_files() {
+        let hold = Data::init(get_root_two_files());
+        let actual_children = Data::get_children(&hold, &hold.current_root);
+        let expected_children = vec![
+            Node {
+                id: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
+                name: "file1".to_string(),
+                size: 800,
+                children: vec![],
+                grouped: vec![],
+                hidden_children: false,
+            },
+            Node {
+                id: Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap(),
+                name: "file2".to_string(),
+                size: 600,
+                children: vec![],
+                grouped: vec![],
+                hidden_children: false,
+            },
         ];
-        let hold = Data::init(data);
+        assert_eq!(expected_children, actual_children);
+    }
+
+    #[test]
+    fn get_children_nested_folders() {
+        let hold = Data::init(get_root_nested_folders());
         let actual_children = Data::get_children(&hold, &hold.current_root);
         let expected_children = vec![Node {
             id: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
             name: "Layer1".to_string(),
-            portion: 2800.0 / 3800.0,
+            size: 2800,
             children: vec![Node {
                 id: Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap(),
                 name: "Layer2".to_string(),
-                portion: 1800.0 / 3800.0,
+                size: 1800,
                 children: vec![Node {
                     id: Uuid::parse_str("fc50112e-5f9d-4ebf-b6a8-023ba619fd0f").unwrap(),
                     name: "file".to_string(),
-                    portion: 800.0 / 3800.0,
+                    size: 800,
                     children: vec![],
+                    grouped: vec![],
+                    hidden_children: false,
                 }],
+                grouped: vec![],
+                hidden_children: false,
             }],
+            grouped: vec![],
+            hidden_children: false,
         }];
         assert_eq!(expected_children, actual_children);
     }
@@ -381,49 +990,21 @@ mod test {
             },
         ];
         let hold = Data::init(data);
-        let expected_order: Vec<NodeLayer> = vec![
-            NodeLayer {
-                id: Uuid::parse_str("fe777276-381f-408b-b41a-bac9b302b9cc").unwrap(),
-                name: "rightlayer2file2".to_string(),
-                portion: 300.0 / 4400.0,
-                layer: 2,
-            },
-            NodeLayer {
-                id: Uuid::parse_str("f2c90c41-4aea-44be-a79d-caea3f0306aa").unwrap(),
-                name: "rightlayer2file1".to_string(),
-                portion: 300.0 / 4400.0,
-                layer: 2,
-            },
-            NodeLayer {
-                id: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
-                name: "leftlayer2file".to_string(),
-                portion: 800.0 / 4400.0,
-                layer: 2,
-            },
-            NodeLayer {
-                id: Uuid::parse_str("219df288-f08b-422b-adf6-59534df7ee91").unwrap(),
-                name: "rightlayer1".to_string(),
-                portion: 1600.0 / 4400.0,
-                layer: 1,
-            },
-            NodeLayer {
-                id: Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap(),
-                name: "leftlayer1".to_string(),
-                portion: 1800.0 / 4400.0,
-                layer: 1,
-            },
-            NodeLayer {
-                id: Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap(),
-                name: "Root".to_string(),
-                portion: 1.0,
-                layer: 0,
-            },
-        ];
         let actual_order = Data::get_paint_order(&hold);
+        let layers: Vec<(&str, u64, u64, u64)> = actual_order
+            .iter()
+            .map(|item| (item.name.as_str(), item.layer, item.offset, item.size))
+            .collect();
+        //Children start where their folder starts and equal sizes fall back to name order
         assert_eq!(
-            expected_order, actual_order,
-            "\nExpected: \n{:?}\nActual:\n{:?}\n",
-            expected_order, actual_order
+            layers,
+            vec![
+                ("leftlayer1", 1, 0, 1800),
+                ("rightlayer1", 1, 1800, 1600),
+                ("leftlayer2file", 2, 0, 800),
+                ("rightlayer2file1", 2, 1800, 300),
+                ("rightlayer2file2", 2, 2100, 300),
+            ]
         );
     }
 
@@ -495,34 +1076,40 @@ mod test {
         let actual_order = Data::get_paint_order(&hold);
         let expected_order: Vec<NodeLayer> = vec![
             NodeLayer {
-                id: Uuid::parse_str("fc50112e-5f9d-4ebf-b6a8-023ba619fd0f").unwrap(),
-                name: "Left3".to_string(),
-                portion: 800.0 / 5800.0,
-                layer: 3,
-            },
-            NodeLayer {
-                id: Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap(),
-                name: "Left2".to_string(),
-                portion: 1800.0 / 5800.0,
-                layer: 2,
+                id: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
+                name: "Left1".to_string(),
+                size: 4800,
+                offset: 0,
+                layer: 1,
+                grouped: vec![],
+                hidden_children: false,
             },
             NodeLayer {
                 id: Uuid::parse_str("6c1cb978-7c4e-4d83-825a-477287f89c69").unwrap(),
                 name: "Right2".to_string(),
-                portion: 2000.0 / 5800.0,
+                size: 2000,
+                offset: 0,
                 layer: 2,
+                grouped: vec![],
+                hidden_children: false,
             },
             NodeLayer {
-                id: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
-                name: "Left1".to_string(),
-                portion: 4800.0 / 5800.0,
-                layer: 1,
+                id: Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap(),
+                name: "Left2".to_string(),
+                size: 1800,
+                offset: 2000,
+                layer: 2,
+                grouped: vec![],
+                hidden_children: false,
             },
             NodeLayer {
-                id: Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap(),
-                name: "Root".to_string(),
-                portion: 1.0,
-                layer: 0,
+                id: Uuid::parse_str("fc50112e-5f9d-4ebf-b6a8-023ba619fd0f").unwrap(),
+                name: "Left3".to_string(),
+                size: 800,
+                offset: 2000,
+                layer: 3,
+                grouped: vec![],
+                hidden_children: false,
             },
         ];
         assert_eq!(
@@ -531,4 +1118,426 @@ mod test {
             expected_order, actual_order
         );
     }
+
+    #[test]
+    fn report_root_two_files() {
+        let hold = Data::init(get_root_two_files());
+        let report = hold.get_report();
+        let paths: Vec<&str> = report.iter().map(|row| row.path.as_str()).collect();
+        assert_eq!(paths, vec!["Root", "Root/file1", "Root/file2"]);
+        assert_eq!(report[0].aggregated_size, 2400);
+        assert_eq!(report[0].own_size, 1000);
+        assert_eq!(report[0].depth, 0);
+        assert_eq!(report[0].file_type, "Folder");
+        assert_eq!(report[1].aggregated_size, 800);
+        assert_eq!(report[1].depth, 1);
+        assert_eq!(report[1].percentage, 800.0 / 2400.0 * 100.0);
+        assert_eq!(report[2].file_type, "Document");
+    }
+
+    #[test]
+    fn top_level_ignores_current_root() {
+        let mut hold = Data::init(get_root_nested_folders());
+        let layer1 = Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap();
+        let file = Uuid::parse_str("fc50112e-5f9d-4ebf-b6a8-023ba619fd0f").unwrap();
+        assert_eq!(hold.get_top_level(&file), Some((layer1, 3)));
+        hold.current_root = Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap();
+        assert_eq!(hold.get_top_level(&file), Some((layer1, 3)));
+        assert_eq!(hold.get_top_level(&hold.overall_root), None);
+        assert_eq!(hold.get_top_level_order(), vec![layer1]);
+    }
+
+    #[test]
+    fn small_siblings_are_grouped() {
+        let mut hold = Data::init(get_root_two_files());
+        hold.group_below = 900;
+        let children = hold.get_children(&hold.current_root);
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].id, Data::group_id(&hold.current_root));
+        assert_eq!(children[0].name, "2 small items");
+        assert_eq!(children[0].size, 1400);
+        assert_eq!(children[0].grouped.len(), 2);
+
+        hold.group_below = 700;
+        let children = hold.get_children(&hold.current_root);
+        assert_eq!(children.len(), 2);
+        assert!(children.iter().all(|child| child.grouped.is_empty()));
+
+        hold.group_below = 900;
+        hold.expanded_groups.insert(hold.current_root);
+        assert_eq!(hold.get_children(&hold.current_root).len(), 2);
+    }
+
+    #[test]
+    fn max_depth_hides_deeper_layers() {
+        let mut hold = Data::init(get_root_nested_folders());
+        hold.max_depth = Some(2);
+        let children = hold.get_children(&hold.current_root);
+        assert!(!children[0].hidden_children);
+        let layer2 = &children[0].children[0];
+        assert!(layer2.children.is_empty());
+        assert!(layer2.hidden_children);
+
+        hold.max_depth = Some(3);
+        let children = hold.get_children(&hold.current_root);
+        let layer2 = &children[0].children[0];
+        assert_eq!(layer2.children.len(), 1);
+        assert!(!layer2.hidden_children);
+        assert!(!layer2.children[0].hidden_children);
+    }
+
+    #[test]
+    fn large_sizes_keep_their_order() {
+        let mut data = get_root_two_files();
+        data[1].size = 40_000_000_000;
+        data[2].size = 40_000_000_001;
+        let hold = Data::init(data);
+        let paint_order = hold.get_paint_order();
+        assert_eq!(paint_order[0].name, "file2");
+        assert_eq!(paint_order[1].offset, 40_000_000_001);
+        assert_eq!(paint_order[1].size, 40_000_000_000);
+    }
+
+    #[test]
+    fn sort_modes_are_deterministic() {
+        let mut data = get_root_two_files();
+        data[1].file.last_modified = 1;
+        data[2].file.name = "File3".to_string();
+        data[2].size = 800;
+        let mut hold = Data::init(data.clone());
+        let names = |hold: &Data| -> Vec<String> {
+            hold.get_children(&hold.current_root)
+                .into_iter()
+                .map(|child| child.name)
+                .collect()
+        };
+        assert_eq!(names(&hold), vec!["File3", "file1"]);
+        hold.sort = SortMode::Name;
+        assert_eq!(names(&hold), vec!["file1", "File3"]);
+        hold.sort = SortMode::LastModified;
+        assert_eq!(names(&hold), vec!["File3", "file1"]);
+        hold.sort = SortMode::Type;
+        assert_eq!(names(&hold), vec!["File3", "file1"]);
+
+        //A folder smaller than both documents still goes first when sorting by type
+        let mut folder = data[0].clone();
+        folder.file.id = Uuid::from_u128(1);
+        folder.file.name = "Folder".to_string();
+        folder.file.file_type = FileType::Folder;
+        folder.file.parent = hold.overall_root;
+        folder.size = 1;
+        data.push(folder);
+        let mut hold = Data::init(data);
+        hold.sort = SortMode::Type;
+        assert_eq!(names(&hold), vec!["Folder", "File3", "file1"]);
+        hold.sort = SortMode::Size;
+        assert_eq!(names(&hold), vec!["File3", "file1", "Folder"]);
+    }
+
+    #[test]
+    fn find_matches_names_in_path_order() {
+        let hold = Data::init(get_root_two_files());
+        let names = |query: &str| -> Vec<String> {
+            hold.find(query)
+                .iter()
+                .map(|id| hold.all_files.get(id).unwrap().file.name.clone())
+                .collect()
+        };
+        assert_eq!(names("FILE"), vec!["file1", "file2"]);
+        assert_eq!(names("2"), vec!["file2"]);
+        assert!(names("root").is_empty());
+        assert!(names("").is_empty());
+    }
+
+    #[test]
+    fn find_folder_by_path() {
+        let hold = Data::init(get_root_two_files());
+        assert_eq!(hold.find_folder("Root"), Some(hold.overall_root));
+        assert_eq!(hold.find_folder("/Root/"), Some(hold.overall_root));
+        assert_eq!(hold.find_folder("Root/file1"), None);
+    }
+
+    //A root with one folder holding two files
+    fn get_root_folder_two_files() -> Vec<FileRow> {
+        vec![
+            FileRow {
+                file: File {
+                    id: Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap(),
+                    parent: Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap(),
+                    name: "Root".to_string(),
+                    file_type: FileType::Folder,
+                    last_modified: 1693063210788,
+                    last_modified_by: "parth".to_string(),
+                    shares: [].to_vec(),
+                },
+                size: 1000,
+            },
+            FileRow {
+                file: File {
+                    id: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
+                    parent: Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap(),
+                    name: "Folder".to_string(),
+                    file_type: FileType::Folder,
+                    last_modified: 1693063210788,
+                    last_modified_by: "parth".to_string(),
+                    shares: [].to_vec(),
+                },
+                size: 1000,
+            },
+            FileRow {
+                file: File {
+                    id: Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap(),
+                    parent: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
+                    name: "file1".to_string(),
+                    file_type: FileType::Document,
+                    last_modified: 1693063210788,
+                    last_modified_by: "parth".to_string(),
+                    shares: [].to_vec(),
+                },
+                size: 800,
+            },
+            FileRow {
+                file: File {
+                    id: Uuid::parse_str("fc50112e-5f9d-4ebf-b6a8-023ba619fd0f").unwrap(),
+                    parent: Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap(),
+                    name: "file2".to_string(),
+                    file_type: FileType::Document,
+                    last_modified: 1693063210788,
+                    last_modified_by: "parth".to_string(),
+                    shares: [].to_vec(),
+                },
+                size: 300,
+            },
+        ]
+    }
+
+    #[test]
+    fn remove_shrinks_ancestors() {
+        let root = Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap();
+        let folder = Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap();
+        let file1 = Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap();
+        let mut hold = Data::init(get_root_folder_two_files());
+        assert_eq!(hold.topmost(&[file1, folder]), vec![folder]);
+
+        hold.remove(&file1);
+        assert_eq!(hold.get_size(&folder), 1300);
+        assert_eq!(hold.get_size(&root), 2300);
+        assert!(!hold.all_files.contains_key(&file1));
+
+        hold.remove(&folder);
+        assert_eq!(hold.get_size(&root), 1000);
+        assert_eq!(hold.all_files.len(), 1);
+        assert!(!hold.folder_sizes.contains_key(&folder));
+    }
+
+    #[test]
+    fn reparent_moves_sizes_between_folders() {
+        let root = Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap();
+        let folder = Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap();
+        let file1 = Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap();
+        let mut hold = Data::init(get_root_folder_two_files());
+        assert!(!hold.can_move(&folder, &folder));
+        assert!(!hold.can_move(&file1, &folder));
+        assert!(hold.can_move(&file1, &root));
+
+        hold.reparent(&file1, &root);
+        assert_eq!(hold.get_size(&folder), 1300);
+        assert_eq!(hold.get_size(&root), 3100);
+        let ids = |hold: &Data, id| -> Vec<Uuid> {
+            hold.get_children(&id)
+                .iter()
+                .map(|child| child.id)
+                .collect()
+        };
+        assert!(ids(&hold, root).contains(&file1));
+        assert!(!ids(&hold, folder).contains(&file1));
+
+        hold.reparent(&file1, &folder);
+        assert_eq!(hold.get_size(&folder), 2100);
+        assert_eq!(hold.get_size(&root), 3100);
+    }
+
+    #[test]
+    fn insert_resize_and_rename_keep_sizes() {
+        let mut hold = Data::init(get_root_two_files());
+        let root = hold.overall_root;
+        let folder = Uuid::parse_str("1d0f4a3e-5c3b-4f0e-9a43-0d7f3b1d2a11").unwrap();
+        let file3 = Uuid::parse_str("6c1cb978-7c4e-4d83-825a-477287f89c69").unwrap();
+        let mut row = get_root_two_files()[0].clone();
+        row.file.id = folder;
+        row.file.parent = root;
+        row.file.name = "Folder".to_string();
+        row.size = 100;
+        assert!(hold.insert(row));
+        let mut row = get_root_two_files()[1].clone();
+        row.file.id = file3;
+        row.file.parent = folder;
+        row.size = 50;
+        assert!(hold.insert(row));
+        assert_eq!(hold.get_size(&folder), 150);
+        assert_eq!(hold.get_size(&root), 2550);
+
+        hold.resize(&file3, 80);
+        hold.resize(&folder, 200);
+        assert_eq!(hold.get_size(&folder), 280);
+        assert_eq!(hold.get_size(&root), 2680);
+
+        hold.rename(&file3, "file3");
+        assert_eq!(hold.get_path(&file3), "Root/Folder/file3");
+        assert_eq!(hold.get_children(&folder)[0].name, "file3");
+    }
+
+    #[test]
+    fn insert_refuses_ids_already_there() {
+        let mut hold = Data::init(get_root_folder_two_files());
+        let root = hold.overall_root;
+        let rows = get_root_folder_two_files();
+        let before = (hold.folder_sizes.clone(), hold.children.clone());
+        assert!(!hold.insert(rows[0].clone()));
+        assert!(!hold.insert(rows[2].clone()));
+        //A file can't go under a document or a folder that isn't there either
+        let mut orphan = rows[3].clone();
+        orphan.file.id = Uuid::from_u128(1);
+        orphan.file.parent = rows[2].file.id;
+        assert!(!hold.insert(orphan.clone()));
+        orphan.file.parent = Uuid::from_u128(2);
+        assert!(!hold.insert(orphan));
+        assert_eq!((hold.folder_sizes, hold.children), before);
+        assert_eq!(hold.all_files.len(), 4);
+        assert_eq!(hold.overall_root, root);
+    }
+
+    #[test]
+    fn update_matches_a_fresh_listing() {
+        let root = Uuid::parse_str("8cac2286-87d0-4df3-b6f7-5c86c4fa928c").unwrap();
+        let a = Uuid::parse_str("1c890596-1df9-4638-b0c1-ec77fdaa7a49").unwrap();
+        let b = Uuid::parse_str("9b052bca-50b4-47b1-8f6a-8a51e3310d86").unwrap();
+        let row = |id: Uuid, parent: Uuid, name: &str, file_type: FileType, size: u64| FileRow {
+            file: File {
+                id,
+                parent,
+                name: name.to_string(),
+                file_type,
+                last_modified: 1693063210788,
+                last_modified_by: "parth".to_string(),
+                shares: [].to_vec(),
+            },
+            size,
+        };
+        let mut hold = Data::init(vec![
+            row(root, root, "Root", FileType::Folder, 10),
+            row(a, root, "A", FileType::Folder, 10),
+            row(b, a, "B", FileType::Folder, 10),
+            row(Uuid::from_u128(1), b, "gone", FileType::Document, 500),
+        ]);
+        //Swapping A and B has to move B out before A can go inside it
+        let listing = vec![
+            row(root, root, "Root", FileType::Folder, 10),
+            row(b, root, "B", FileType::Folder, 10),
+            row(a, b, "A renamed", FileType::Folder, 10),
+            row(Uuid::from_u128(2), a, "new", FileType::Document, 300),
+        ];
+        let changed = hold.update(listing.clone());
+        assert_eq!(changed, [a, b, Uuid::from_u128(2)].into_iter().collect());
+
+        let fresh = Data::init(listing);
+        assert_eq!(hold.all_files, fresh.all_files);
+        assert_eq!(hold.folder_sizes, fresh.folder_sizes);
+        assert_eq!(hold.children, fresh.children);
+    }
+
+    #[test]
+    fn update_skips_files_whose_folder_is_not_listed() {
+        let mut hold = Data::init(get_root_folder_two_files());
+        let root = hold.overall_root;
+        let mut listing = get_root_folder_two_files();
+        //file1 has synced but the folder it moved into hasn't yet
+        listing[2].file.parent = Uuid::from_u128(1);
+        let mut new = listing[3].clone();
+        new.file.id = Uuid::from_u128(2);
+        new.file.parent = Uuid::from_u128(1);
+        listing.push(new);
+
+        let changed = hold.update(listing);
+        assert!(changed.is_empty());
+        assert!(!hold.all_files.contains_key(&Uuid::from_u128(2)));
+        assert_eq!(hold.all_files.len(), 3);
+        assert_eq!(hold.get_size(&root), 2300);
+    }
+
+    #[test]
+    fn streaming_matches_a_parsed_list() {
+        let json = std::fs::read_to_string("parth-doc-data.json").unwrap();
+        let rows: Vec<FileRow> = serde_json::from_str(&json).unwrap();
+        let listed = Data::init(rows);
+        let progress = Progress::default();
+        let streamed = Data::from_reader(json.as_bytes(), &progress).unwrap();
+        assert_eq!(streamed.overall_root, listed.overall_root);
+        assert_eq!(streamed.all_files, listed.all_files);
+        assert_eq!(streamed.folder_sizes, listed.folder_sizes);
+        assert_eq!(streamed.children, listed.children);
+        assert_eq!(
+            progress
+                .rows_parsed
+                .load(std::sync::atomic::Ordering::Relaxed),
+            listed.all_files.len()
+        );
+    }
+
+    #[test]
+    fn compressed_snapshots_match_plain_ones() {
+        use flate2::{write::GzEncoder, Compression};
+        use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+        use std::io::Write;
+
+        let gzip = |contents: &[u8]| {
+            let mut encoder = GzEncoder::new(vec![], Compression::fast());
+            encoder.write_all(contents).unwrap();
+            encoder.finish().unwrap()
+        };
+        let zstd = |contents: &[u8]| compress_to_vec(contents, CompressionLevel::Fastest);
+        let json = std::fs::read("parth-doc-data.json").unwrap();
+        let plain = Data::from_file("parth-doc-data.json", &Progress::default()).unwrap();
+        let mut binary = vec![];
+        crate::snapshot::write(&plain.rows(), &mut binary).unwrap();
+        for (name, compressed) in [
+            ("json.gz", gzip(&json)),
+            ("snap.gz", gzip(&binary)),
+            ("json.zst", zstd(&json)),
+            ("snap.zst", zstd(&binary)),
+        ] {
+            //Named after the process too, so parallel runs don't trip over each other's files
+            let path = std::env::temp_dir().join(format!(
+                "space_inspector_test_{}.{}",
+                std::process::id(),
+                name
+            ));
+            std::fs::write(&path, compressed).unwrap();
+            let read = Data::from_file(path.to_str().unwrap(), &Progress::default()).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(read.all_files, plain.all_files);
+            assert_eq!(read.folder_sizes, plain.folder_sizes);
+        }
+    }
+
+    #[test]
+    fn start_is_read_even_when_it_trickles_in() {
+        //Hands over one byte per read, like a slow decoder or a pipe can
+        struct Trickle<'a>(&'a [u8]);
+        impl std::io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let Some((first, rest)) = self.0.split_first() else {
+                    return Ok(0);
+                };
+                buf[0] = *first;
+                self.0 = rest;
+                Ok(1)
+            }
+        }
+        assert_eq!(
+            super::read_start(&mut Trickle(b"SISN\x01"), 4).unwrap(),
+            b"SISN"
+        );
+        assert_eq!(super::read_start(&mut Trickle(b"[]"), 4).unwrap(), b"[]");
+    }
 }
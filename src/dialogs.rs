@@ -0,0 +1,461 @@
+use crate::{MyApp, Segment};
+use eframe::egui::{self, Align, Grid, ScrollArea, Ui};
+use lb_rs::model::file_metadata::FileType;
+use lb_rs::model::usage::bytes_to_human;
+use lb_rs::Uuid;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//Something picked from a segment's right click menu, carried out once the chart is drawn
+pub enum SegmentAction {
+    SetRoot(Uuid),
+    Expand(Uuid),
+    Details(Uuid),
+    Reveal(Uuid),
+    Open(Uuid),
+    Rename(Uuid),
+    Move(Uuid),
+    Delete(Uuid),
+}
+
+//How long ago a millisecond timestamp was, in the largest whole unit
+fn age(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or_default();
+    let seconds = now.saturating_sub(timestamp) / 1000;
+    let (amount, unit) = match seconds {
+        0..=59 => (seconds, "second"),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    format!(
+        "{} {}{} ago",
+        amount,
+        unit,
+        if amount == 1 { "" } else { "s" }
+    )
+}
+
+impl MyApp {
+    pub fn segment_menu(&self, ui: &mut Ui, segment: &Segment) -> Option<SegmentAction> {
+        let mut action = None;
+        if segment.is_group {
+            if ui.button("Show small items").clicked() {
+                action = Some(SegmentAction::Expand(segment.parent));
+            }
+            if ui.button("Copy name").clicked() {
+                ui.ctx().copy_text(segment.name.clone());
+                ui.close_menu();
+            }
+            return action;
+        }
+
+        if ui.button("Copy name").clicked() {
+            ui.ctx().copy_text(segment.name.clone());
+            ui.close_menu();
+        }
+        if ui.button("Copy path").clicked() {
+            ui.ctx().copy_text(self.data.get_path(&segment.id));
+            ui.close_menu();
+        }
+        if ui.button("Copy id").clicked() {
+            ui.ctx().copy_text(segment.id.to_string());
+            ui.close_menu();
+        }
+        ui.separator();
+        if ui.button("Details").clicked() {
+            action = Some(SegmentAction::Details(segment.id));
+        }
+        if ui
+            .add_enabled(segment.is_folder, egui::Button::new("Set as root"))
+            .clicked()
+        {
+            action = Some(SegmentAction::SetRoot(segment.id));
+        }
+        if ui
+            .add_enabled(
+                segment.parent != self.data.current_root,
+                egui::Button::new("Open parent"),
+            )
+            .clicked()
+        {
+            action = Some(SegmentAction::SetRoot(segment.parent));
+        }
+        if ui.button("Reveal in table").clicked() {
+            action = Some(SegmentAction::Reveal(segment.id));
+        }
+
+        if self.source.is_live() {
+            ui.separator();
+            if ui
+                .add_enabled(!segment.is_folder, egui::Button::new("Open"))
+                .clicked()
+            {
+                action = Some(SegmentAction::Open(segment.id));
+            }
+            if ui.button("Rename…").clicked() {
+                action = Some(SegmentAction::Rename(segment.id));
+            }
+            if ui.button("Move…").clicked() {
+                action = Some(SegmentAction::Move(segment.id));
+            }
+        }
+        let delete = if self.source.is_live() {
+            "Delete…"
+        } else {
+            "Delete (dry run)…"
+        };
+        if ui.button(delete).clicked() {
+            action = Some(SegmentAction::Delete(segment.id));
+        }
+        if action.is_some() {
+            ui.close_menu();
+        }
+        action
+    }
+
+    pub fn apply_action(&mut self, action: SegmentAction) {
+        match action {
+            SegmentAction::SetRoot(id) => self.change_root(id),
+            SegmentAction::Expand(parent) => {
+                self.data.expanded_groups.insert(parent);
+                self.paint_order = vec![];
+            }
+            SegmentAction::Details(id) => self.details = Some(id),
+            SegmentAction::Reveal(id) => {
+                self.show_table = true;
+                self.table_reveal = Some(id);
+            }
+            SegmentAction::Open(id) => {
+                let name = self.data.all_files.get(&id).unwrap().file.name.clone();
+                self.action_status = self.source.open_file(id, &name).err();
+            }
+            SegmentAction::Rename(id) => {
+                let name = self.data.all_files.get(&id).unwrap().file.name.clone();
+                self.renaming = Some((id, name));
+            }
+            SegmentAction::Move(id) => {
+                let parent = self.data.all_files.get(&id).unwrap().file.parent;
+                self.moving = Some((id, self.data.get_path(&parent)));
+            }
+            SegmentAction::Delete(id) => {
+                self.deleting = if self.selected.contains(&id) {
+                    self.selected.iter().copied().collect()
+                } else {
+                    vec![id]
+                };
+            }
+        }
+    }
+
+    //Runs a change against the source, then picks up the result from it
+    fn write_back(&mut self, result: Result<(), String>) -> bool {
+        match result.and_then(|()| self.reload()) {
+            Ok(_) => {
+                self.action_status = None;
+                true
+            }
+            Err(err) => {
+                self.action_status = Some(err);
+                false
+            }
+        }
+    }
+
+    pub fn show_dialogs(&mut self, ctx: &egui::Context) {
+        self.details_window(ctx);
+        self.table_window(ctx);
+        self.rename_window(ctx);
+        self.move_window(ctx);
+        self.delete_window(ctx);
+    }
+
+    fn details_window(&mut self, ctx: &egui::Context) {
+        let Some(row) = self.details.and_then(|id| self.data.all_files.get(&id)) else {
+            return;
+        };
+        let id = row.file.id;
+        let size = self.data.get_size(&id);
+        let root_size = self.data.get_size(&self.data.current_root).max(1);
+        let mut open = true;
+        egui::Window::new("Details")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                Grid::new("details").num_columns(2).show(ui, |ui| {
+                    ui.label("Name");
+                    ui.label(&row.file.name);
+                    ui.end_row();
+                    ui.label("Path");
+                    ui.label(self.data.get_path(&id));
+                    ui.end_row();
+                    ui.label("Id");
+                    ui.label(id.to_string());
+                    ui.end_row();
+                    ui.label("Type");
+                    ui.label(match row.file.file_type {
+                        FileType::Document => "Document",
+                        FileType::Folder => "Folder",
+                        FileType::Link { .. } => "Link",
+                    });
+                    ui.end_row();
+                    ui.label("Size");
+                    ui.label(bytes_to_human(size));
+                    ui.end_row();
+                    if row.file.is_folder() {
+                        ui.label("Own size");
+                        ui.label(bytes_to_human(row.size));
+                        ui.end_row();
+                    }
+                    ui.label("Share of root");
+                    ui.label(format!("{:.1}%", size as f64 / root_size as f64 * 100.0));
+                    ui.end_row();
+                    ui.label("Last modified");
+                    ui.label(format!(
+                        "{} by {}",
+                        age(row.file.last_modified),
+                        row.file.last_modified_by
+                    ));
+                    ui.end_row();
+                });
+            });
+        if !open {
+            self.details = None;
+        }
+    }
+
+    fn table_window(&mut self, ctx: &egui::Context) {
+        if !self.show_table {
+            return;
+        }
+        if self.table.is_empty() {
+            self.table = self.data.get_report();
+        }
+        let reveal = self.table_reveal.take();
+        let mut open = true;
+        egui::Window::new("Table")
+            .open(&mut open)
+            .default_size([500.0, 400.0])
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("table")
+                        .num_columns(3)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong("Path");
+                            ui.strong("Size");
+                            ui.strong("Share");
+                            ui.end_row();
+                            for row in &self.table {
+                                let selected = self.table_selected == Some(row.id);
+                                let response = ui.selectable_label(selected, &row.path);
+                                if response.clicked() {
+                                    self.table_selected = Some(row.id);
+                                }
+                                if reveal == Some(row.id) {
+                                    self.table_selected = Some(row.id);
+                                    response.scroll_to_me(Some(Align::Center));
+                                }
+                                ui.label(bytes_to_human(row.aggregated_size));
+                                ui.label(format!("{:.1}%", row.percentage));
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+        if !open {
+            self.show_table = false;
+        }
+    }
+
+    fn rename_window(&mut self, ctx: &egui::Context) {
+        let Some((id, mut name)) = self.renaming.take() else {
+            return;
+        };
+        let mut open = true;
+        let mut done = false;
+        egui::Window::new("Rename")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.text_edit_singleline(&mut name);
+                if let Some(status) = &self.action_status {
+                    ui.label(status);
+                }
+                if ui.button("Rename").clicked() {
+                    done = self.write_back(self.source.rename(id, &name));
+                }
+            });
+        if open && !done {
+            self.renaming = Some((id, name));
+        }
+    }
+
+    fn move_window(&mut self, ctx: &egui::Context) {
+        let Some((id, mut destination)) = self.moving.take() else {
+            return;
+        };
+        let mut open = true;
+        let mut done = false;
+        egui::Window::new("Move")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Move {} into", self.data.get_path(&id)));
+                ui.text_edit_singleline(&mut destination);
+                if let Some(status) = &self.action_status {
+                    ui.label(status);
+                }
+                if ui.button("Move").clicked() {
+                    match self.data.find_folder(&destination) {
+                        Some(parent) if self.data.can_move(&id, &parent) => {
+                            self.action_status = None;
+                            done = self.move_files(&[id], parent);
+                        }
+                        Some(_) => {
+                            self.action_status = Some(format!("can't move it into {}", destination))
+                        }
+                        None => {
+                            self.action_status =
+                                Some(format!("there's no folder at {}", destination))
+                        }
+                    }
+                }
+            });
+        if open && !done {
+            self.moving = Some((id, destination));
+        }
+    }
+
+    //Moves through the source, or for a JSON snapshot only rearranges the view
+    pub fn move_files(&mut self, ids: &[Uuid], parent: Uuid) -> bool {
+        let ids: Vec<Uuid> = self
+            .data
+            .topmost(ids)
+            .into_iter()
+            .filter(|id| self.data.can_move(id, &parent))
+            .collect();
+        self.paint_order = vec![];
+        for id in &ids {
+            if self.source.is_live() {
+                if let Err(err) = self.source.move_file(*id, parent) {
+                    self.action_status =
+                        Some(format!("Couldn't move {}: {}", self.data.get_path(id), err));
+                    return false;
+                }
+            }
+            self.data.reparent(id, &parent);
+        }
+        if !self.source.is_live() && !ids.is_empty() {
+            self.action_status = Some(format!(
+                "Dry run: moved {} in the view, the snapshot is unchanged",
+                if ids.len() == 1 {
+                    "1 item".to_owned()
+                } else {
+                    format!("{} items", ids.len())
+                }
+            ));
+        }
+        true
+    }
+
+    //Deletes through the source, or for a JSON snapshot only takes the files out of the view
+    fn delete(&mut self, ids: &[Uuid]) {
+        let mut removed = 0;
+        for id in ids {
+            if self.source.is_live() {
+                if let Err(err) = self.source.delete(*id) {
+                    self.action_status = Some(format!(
+                        "Couldn't delete {}: {}",
+                        self.data.get_path(id),
+                        err
+                    ));
+                    break;
+                }
+            }
+            removed += self.data.get_size(id);
+            self.data.remove(id);
+        }
+        if self.action_status.is_none() && !self.source.is_live() {
+            self.action_status = Some(format!(
+                "Dry run: took {} out of the view, the snapshot is unchanged",
+                bytes_to_human(removed)
+            ));
+        }
+        self.forget_missing();
+    }
+
+    //Lets go of any ids that are no longer in the data, after files were deleted here or elsewhere
+    pub fn forget_missing(&mut self) {
+        let files = &self.data.all_files;
+        self.selected.retain(|id| files.contains_key(id));
+        self.search_matches.retain(|id| files.contains_key(id));
+        self.deleting.retain(|id| files.contains_key(id));
+        self.back.retain(|id| files.contains_key(id));
+        self.forward.retain(|id| files.contains_key(id));
+        if self.details.is_some_and(|id| !files.contains_key(&id)) {
+            self.details = None;
+        }
+        if self
+            .renaming
+            .as_ref()
+            .is_some_and(|(id, _)| !files.contains_key(id))
+        {
+            self.renaming = None;
+        }
+        if self
+            .moving
+            .as_ref()
+            .is_some_and(|(id, _)| !files.contains_key(id))
+        {
+            self.moving = None;
+        }
+        self.paint_order = vec![];
+    }
+
+    fn delete_window(&mut self, ctx: &egui::Context) {
+        if self.deleting.is_empty() {
+            return;
+        }
+        let ids = self.data.topmost(&self.deleting);
+        let reclaimed: u64 = ids.iter().map(|id| self.data.get_size(id)).sum();
+        let mut open = true;
+        let mut confirmed = false;
+        egui::Window::new("Delete")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                for id in ids.iter().take(10) {
+                    ui.label(self.data.get_path(id));
+                }
+                if ids.len() > 10 {
+                    ui.label(format!("and {} more", ids.len() - 10));
+                }
+                ui.separator();
+                ui.label(format!(
+                    "{} {} will free {}",
+                    ids.len(),
+                    if ids.len() == 1 { "item" } else { "items" },
+                    bytes_to_human(reclaimed)
+                ));
+                if !self.source.is_live() {
+                    ui.label("This is a JSON snapshot, so nothing will be deleted from disk");
+                }
+                let button = if self.source.is_live() {
+                    "Delete"
+                } else {
+                    "Remove from view"
+                };
+                confirmed = ui.button(button).clicked();
+            });
+        if confirmed {
+            self.action_status = None;
+            self.delete(&ids);
+        }
+        if !open || confirmed {
+            self.deleting = vec![];
+        }
+    }
+}
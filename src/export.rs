@@ -0,0 +1,421 @@
+use crate::data::ReportRow;
+use crate::palette::{label_color, Chrome};
+use crate::Segment;
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use eframe::egui::{pos2, vec2, Color32, FontDefinitions, Pos2, Rect};
+use image::{Rgba, RgbaImage};
+use lb_rs::model::usage::bytes_to_human;
+use std::fs;
+
+pub const ROOT_BAR_HEIGHT: f32 = 40.0;
+const LEGEND_MARGIN: f32 = 10.0;
+const LEGEND_ROW: f32 = 18.0;
+const LABEL_SIZE: f32 = 12.0;
+const ROOT_TEXT_SIZE: f32 = 15.0;
+//Same cutoff the window uses before it bothers drawing a name
+const MIN_LABELED_WIDTH: f32 = 50.0;
+
+//A laid out view of the current root, ready to be written to disk
+pub struct Chart {
+    pub width: f32,
+    pub height: f32,
+    pub root_bar: Rect,
+    pub root_name: String,
+    pub root_size: u64,
+    pub chrome: Chrome,
+    pub segments: Vec<Segment>,
+}
+
+//A small arrow at the top of a slice whose contents were cut off by the depth limit
+pub fn hidden_marker(segment: &Segment) -> Option<[Pos2; 3]> {
+    if !segment.hidden_children || segment.rect.width() < 12.0 {
+        return None;
+    }
+    let center = segment.rect.center_top().x;
+    let top = segment.rect.min.y;
+    Some([
+        pos2(center - 4.0, top + 7.0),
+        pos2(center + 4.0, top + 7.0),
+        pos2(center, top + 2.0),
+    ])
+}
+
+struct LegendEntry {
+    color: Color32,
+    text: String,
+}
+
+pub fn legend_height(entries: usize) -> f32 {
+    LEGEND_MARGIN * 2.0 + LEGEND_ROW * (entries + 1) as f32
+}
+
+impl Chart {
+    //Top level slices plus their share of the root, listed in paint order
+    fn legend(&self) -> Vec<LegendEntry> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.layer == 1)
+            .map(|segment| LegendEntry {
+                color: segment.color,
+                text: format!(
+                    "{} - {} ({:.1}%)",
+                    segment.name,
+                    bytes_to_human(segment.size),
+                    segment.size as f64 / self.root_size.max(1) as f64 * 100.0
+                ),
+            })
+            .collect()
+    }
+
+    fn root_text(&self) -> String {
+        format!("{} - {}", self.root_name, bytes_to_human(self.root_size))
+    }
+}
+
+fn hex(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn to_svg(chart: &Chart) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = chart.width,
+        h = chart.height
+    );
+    svg += &format!(
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+        chart.width,
+        chart.height,
+        hex(chart.chrome.background)
+    );
+
+    svg += "<defs>\n";
+    for (index, segment) in chart.segments.iter().enumerate() {
+        svg += &format!(
+            "<clipPath id=\"clip{}\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/></clipPath>\n",
+            index,
+            segment.rect.min.x,
+            segment.rect.min.y,
+            segment.rect.width(),
+            segment.rect.height()
+        );
+    }
+    svg += "</defs>\n";
+
+    for (index, segment) in chart.segments.iter().enumerate() {
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"0.5\"><title>{} ({})</title></rect>\n",
+            segment.rect.min.x,
+            segment.rect.min.y,
+            segment.rect.width(),
+            segment.rect.height(),
+            hex(segment.color),
+            hex(chart.chrome.border),
+            escape(&segment.name),
+            bytes_to_human(segment.size)
+        );
+        if segment.rect.width() >= MIN_LABELED_WIDTH {
+            svg += &format!(
+                "<text x=\"{}\" y=\"{}\" font-family=\"monospace\" font-size=\"{}\" dominant-baseline=\"middle\" fill=\"{}\" clip-path=\"url(#clip{})\">{}</text>\n",
+                segment.rect.min.x + 5.0,
+                segment.rect.center().y,
+                LABEL_SIZE,
+                hex(label_color(segment.color)),
+                index,
+                escape(&segment.name)
+            );
+        }
+        if let Some(marker) = hidden_marker(segment) {
+            svg += &format!(
+                "<polygon points=\"{},{} {},{} {},{}\" fill=\"{}\"/>\n",
+                marker[0].x,
+                marker[0].y,
+                marker[1].x,
+                marker[1].y,
+                marker[2].x,
+                marker[2].y,
+                hex(label_color(segment.color))
+            );
+        }
+    }
+
+    svg += &format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"0.5\"/>\n",
+        chart.root_bar.min.x,
+        chart.root_bar.min.y,
+        chart.root_bar.width(),
+        chart.root_bar.height(),
+        hex(chart.chrome.root_bar),
+        hex(chart.chrome.border)
+    );
+    svg += &format!(
+        "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"middle\" fill=\"{}\">{}</text>\n",
+        chart.root_bar.center().x,
+        chart.root_bar.center().y,
+        ROOT_TEXT_SIZE,
+        hex(chart.chrome.text),
+        escape(&chart.root_text())
+    );
+
+    svg += &format!(
+        "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" font-weight=\"bold\" dominant-baseline=\"hanging\" fill=\"{}\">Legend</text>\n",
+        LEGEND_MARGIN,
+        LEGEND_MARGIN,
+        LABEL_SIZE,
+        hex(chart.chrome.text)
+    );
+    for (row, entry) in chart.legend().iter().enumerate() {
+        let y = LEGEND_MARGIN + LEGEND_ROW * (row + 1) as f32;
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"0.5\"/>\n",
+            LEGEND_MARGIN,
+            y,
+            LABEL_SIZE,
+            LABEL_SIZE,
+            hex(entry.color),
+            hex(chart.chrome.border)
+        );
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" dominant-baseline=\"hanging\" fill=\"{}\">{}</text>\n",
+            LEGEND_MARGIN + LABEL_SIZE + 6.0,
+            y,
+            LABEL_SIZE,
+            hex(chart.chrome.text),
+            escape(&entry.text)
+        );
+    }
+
+    svg += "</svg>\n";
+    svg
+}
+
+//Rasterizes with the same fonts egui ships with so exports look like the window
+struct Canvas<'a> {
+    image: RgbaImage,
+    monospace: FontRef<'a>,
+    proportional: FontRef<'a>,
+}
+
+impl<'a> Canvas<'a> {
+    fn blend(&mut self, x: i64, y: i64, color: Color32, coverage: f32) {
+        if x < 0 || y < 0 || x >= self.image.width() as i64 || y >= self.image.height() as i64 {
+            return;
+        }
+        let pixel = self.image.get_pixel_mut(x as u32, y as u32);
+        let alpha = coverage.clamp(0.0, 1.0);
+        let mix = |old: u8, new: u8| (old as f32 * (1.0 - alpha) + new as f32 * alpha) as u8;
+        *pixel = Rgba([
+            mix(pixel[0], color.r()),
+            mix(pixel[1], color.g()),
+            mix(pixel[2], color.b()),
+            255,
+        ]);
+    }
+
+    fn fill(&mut self, rect: Rect, color: Color32) {
+        for y in rect.min.y.round() as i64..rect.max.y.round() as i64 {
+            for x in rect.min.x.round() as i64..rect.max.x.round() as i64 {
+                self.blend(x, y, color, 1.0);
+            }
+        }
+    }
+
+    //Only handles the flat bottomed triangles hidden_marker makes
+    fn triangle(&mut self, points: [Pos2; 3], color: Color32) {
+        let (left, right, apex) = (points[0], points[1], points[2]);
+        let height = left.y - apex.y;
+        for row in 0..height.round() as i64 {
+            let spread = (right.x - left.x) / 2.0 * (row as f32 / height);
+            for x in (apex.x - spread).round() as i64..=(apex.x + spread).round() as i64 {
+                self.blend(x, apex.y.round() as i64 + row, color, 1.0);
+            }
+        }
+    }
+
+    fn outline(&mut self, rect: Rect, color: Color32) {
+        let (left, right) = (rect.min.x.round() as i64, rect.max.x.round() as i64);
+        let (top, bottom) = (rect.min.y.round() as i64, rect.max.y.round() as i64);
+        for x in left..=right {
+            self.blend(x, top, color, 1.0);
+            self.blend(x, bottom, color, 1.0);
+        }
+        for y in top..=bottom {
+            self.blend(left, y, color, 1.0);
+            self.blend(right, y, color, 1.0);
+        }
+    }
+
+    //Draws text with its vertical center on `start.y`, cutting it off at `max_width`
+    fn text(
+        &mut self,
+        text: &str,
+        monospace: bool,
+        size: f32,
+        start: Pos2,
+        max_width: f32,
+        color: Color32,
+    ) {
+        let font = if monospace {
+            self.monospace.clone()
+        } else {
+            self.proportional.clone()
+        };
+        let scaled = font.as_scaled(PxScale::from(size));
+        let baseline = start.y + (scaled.ascent() + scaled.descent()) / 2.0;
+        let mut caret = start.x;
+        for character in text.chars() {
+            let glyph_id = font.glyph_id(character);
+            let advance = scaled.h_advance(glyph_id);
+            if caret + advance > start.x + max_width {
+                break;
+            }
+            let glyph = glyph_id.with_scale_and_position(size, ab_glyph::point(caret, baseline));
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|gx, gy, coverage| {
+                    self.blend(
+                        bounds.min.x as i64 + gx as i64,
+                        bounds.min.y as i64 + gy as i64,
+                        color,
+                        coverage,
+                    );
+                });
+            }
+            caret += advance;
+        }
+    }
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        let scaled = self.proportional.as_scaled(PxScale::from(size));
+        text.chars()
+            .map(|character| scaled.h_advance(self.proportional.glyph_id(character)))
+            .sum()
+    }
+}
+
+pub fn to_png(chart: &Chart) -> RgbaImage {
+    let fonts = FontDefinitions::default();
+    let mut canvas = Canvas {
+        image: RgbaImage::from_pixel(
+            chart.width.ceil() as u32,
+            chart.height.ceil() as u32,
+            Rgba(chart.chrome.background.to_array()),
+        ),
+        monospace: FontRef::try_from_slice(&fonts.font_data["Hack"].font)
+            .expect("egui ships a valid monospace font"),
+        proportional: FontRef::try_from_slice(&fonts.font_data["Ubuntu-Light"].font)
+            .expect("egui ships a valid proportional font"),
+    };
+
+    for segment in &chart.segments {
+        canvas.fill(segment.rect, segment.color);
+        canvas.outline(segment.rect, chart.chrome.border);
+        if let Some(marker) = hidden_marker(segment) {
+            canvas.triangle(marker, label_color(segment.color));
+        }
+        if segment.rect.width() >= MIN_LABELED_WIDTH {
+            canvas.text(
+                &segment.name,
+                true,
+                LABEL_SIZE,
+                pos2(segment.rect.min.x + 5.0, segment.rect.center().y),
+                segment.rect.width() - 10.0,
+                label_color(segment.color),
+            );
+        }
+    }
+
+    canvas.fill(chart.root_bar, chart.chrome.root_bar);
+    canvas.outline(chart.root_bar, chart.chrome.border);
+    let root_text = chart.root_text();
+    let root_text_width = canvas.text_width(&root_text, ROOT_TEXT_SIZE);
+    canvas.text(
+        &root_text,
+        false,
+        ROOT_TEXT_SIZE,
+        pos2(
+            chart.root_bar.center().x - root_text_width / 2.0,
+            chart.root_bar.center().y,
+        ),
+        chart.width,
+        chart.chrome.text,
+    );
+
+    canvas.text(
+        "Legend",
+        false,
+        LABEL_SIZE,
+        pos2(LEGEND_MARGIN, LEGEND_MARGIN + LABEL_SIZE / 2.0),
+        chart.width,
+        chart.chrome.text,
+    );
+    for (row, entry) in chart.legend().iter().enumerate() {
+        let y = LEGEND_MARGIN + LEGEND_ROW * (row + 1) as f32;
+        let swatch = Rect::from_min_size(pos2(LEGEND_MARGIN, y), vec2(LABEL_SIZE, LABEL_SIZE));
+        canvas.fill(swatch, entry.color);
+        canvas.outline(swatch, chart.chrome.border);
+        canvas.text(
+            &entry.text,
+            false,
+            LABEL_SIZE,
+            pos2(LEGEND_MARGIN + LABEL_SIZE + 6.0, y + LABEL_SIZE / 2.0),
+            chart.width,
+            chart.chrome.text,
+        );
+    }
+
+    canvas.image
+}
+
+pub fn save_svg(chart: &Chart, path: &str) -> std::io::Result<()> {
+    fs::write(path, to_svg(chart))
+}
+
+pub fn save_png(chart: &Chart, path: &str) -> image::ImageResult<()> {
+    to_png(chart).save_with_format(path, image::ImageFormat::Png)
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+pub fn to_csv(report: &[ReportRow]) -> String {
+    let mut csv =
+        "id,path,type,own_size,aggregated_size,percentage,depth,last_modified,last_modified_by\n"
+            .to_owned();
+    for row in report {
+        csv += &format!(
+            "{},{},{},{},{},{:.4},{},{},{}\n",
+            row.id,
+            csv_field(&row.path),
+            row.file_type,
+            row.own_size,
+            row.aggregated_size,
+            row.percentage,
+            row.depth,
+            row.last_modified,
+            csv_field(&row.last_modified_by)
+        );
+    }
+    csv
+}
+
+pub fn save_csv(report: &[ReportRow], path: &str) -> std::io::Result<()> {
+    fs::write(path, to_csv(report))
+}
+
+pub fn save_json(report: &[ReportRow], path: &str) -> std::io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(report)?)
+}
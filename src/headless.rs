@@ -0,0 +1,190 @@
+use crate::palette::{PaletteKind, Theme};
+use crate::settings::SortMode;
+use crate::{export, settings, snapshot, source, MyApp, DEFAULT_INPUT};
+
+const USAGE: &str = "usage: storage_system [--input <file>] [--width <px>] [--layer-height <px>] [--svg <file>] [--png <file>] [--csv <file>] [--json <file>] [--snapshot <file>] [--theme light|dark] [--sort size|name|modified|type] [--palette classic|viridis|okabe-ito|<file>]";
+
+//Settings for running without a window, parsed from the command line
+struct HeadlessArgs {
+    input: String,
+    width: f32,
+    layer_height: f32,
+    theme: Theme,
+    sort: SortMode,
+    palette: String,
+    svg: Option<String>,
+    png: Option<String>,
+    csv: Option<String>,
+    json: Option<String>,
+    //Converts the input to the binary snapshot format
+    snapshot: Option<String>,
+}
+
+//Flags that write a file, any of them means running without a window
+const EXPORT_FLAGS: [&str; 5] = ["--svg", "--png", "--csv", "--json", "--snapshot"];
+
+//Anything else, like no arguments or `--help`, opens the window instead
+pub fn wants_export(args: &[String]) -> bool {
+    args.iter().any(|arg| EXPORT_FLAGS.contains(&arg.as_str()))
+}
+
+fn parse(args: &[String]) -> Result<HeadlessArgs, String> {
+    let mut parsed = HeadlessArgs {
+        input: DEFAULT_INPUT.to_owned(),
+        width: 1500.0,
+        layer_height: 50.0,
+        theme: Theme::Light,
+        sort: SortMode::Size,
+        palette: "classic".to_owned(),
+        svg: None,
+        png: None,
+        csv: None,
+        json: None,
+        snapshot: None,
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or(format!("{} needs a value\n{}", flag, USAGE))
+        };
+        match flag.as_str() {
+            "--input" => parsed.input = value()?,
+            "--width" => {
+                parsed.width = value()?
+                    .parse()
+                    .map_err(|_| format!("--width must be a number\n{}", USAGE))?
+            }
+            "--layer-height" => {
+                parsed.layer_height = value()?
+                    .parse()
+                    .map_err(|_| format!("--layer-height must be a number\n{}", USAGE))?
+            }
+            "--theme" => {
+                parsed.theme = match value()?.as_str() {
+                    "light" => Theme::Light,
+                    "dark" => Theme::Dark,
+                    _ => return Err(format!("--theme must be light or dark\n{}", USAGE)),
+                }
+            }
+            "--sort" => {
+                parsed.sort = match value()?.as_str() {
+                    "size" => SortMode::Size,
+                    "name" => SortMode::Name,
+                    "modified" => SortMode::LastModified,
+                    "type" => SortMode::Type,
+                    _ => {
+                        return Err(format!(
+                            "--sort must be size, name, modified or type\n{}",
+                            USAGE
+                        ))
+                    }
+                }
+            }
+            "--palette" => parsed.palette = value()?,
+            "--svg" => parsed.svg = Some(value()?),
+            "--png" => parsed.png = Some(value()?),
+            "--csv" => parsed.csv = Some(value()?),
+            "--json" => parsed.json = Some(value()?),
+            "--snapshot" => parsed.snapshot = Some(value()?),
+            _ => return Err(format!("unknown argument {}\n{}", flag, USAGE)),
+        }
+    }
+    if parsed.svg.is_none()
+        && parsed.png.is_none()
+        && parsed.csv.is_none()
+        && parsed.json.is_none()
+        && parsed.snapshot.is_none()
+    {
+        return Err(format!("nothing to export\n{}", USAGE));
+    }
+    Ok(parsed)
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = parse(args)?;
+    let (palette, custom_palette) = match args.palette.as_str() {
+        "classic" => (PaletteKind::Classic, String::new()),
+        "viridis" => (PaletteKind::Viridis, String::new()),
+        "okabe-ito" => (PaletteKind::OkabeIto, String::new()),
+        path => (PaletteKind::Custom, path.to_owned()),
+    };
+    let settings = settings::Settings {
+        layer_height: args.layer_height,
+        source: args.input,
+        theme: args.theme,
+        sort: args.sort,
+        palette,
+        custom_palette,
+        ..Default::default()
+    };
+    let source = source::Source::open(&settings.source)?;
+    let data = source.load_data(&Default::default())?;
+    if !data.all_files.contains_key(&data.overall_root) {
+        return Err(format!("{} has no root folder to chart", settings.source));
+    }
+    let mut app = MyApp::new(source, data, settings, None);
+    if let Some(err) = &app.palette_status {
+        return Err(err.clone());
+    }
+    let chart = app.chart(args.width);
+    let report = app.data.get_report();
+
+    if let Some(path) = &args.svg {
+        export::save_svg(&chart, path)
+            .map_err(|err| format!("couldn't write {}: {}", path, err))?;
+    }
+    if let Some(path) = &args.png {
+        export::save_png(&chart, path)
+            .map_err(|err| format!("couldn't write {}: {}", path, err))?;
+    }
+    if let Some(path) = &args.csv {
+        export::save_csv(&report, path)
+            .map_err(|err| format!("couldn't write {}: {}", path, err))?;
+    }
+    if let Some(path) = &args.json {
+        export::save_json(&report, path)
+            .map_err(|err| format!("couldn't write {}: {}", path, err))?;
+    }
+    if let Some(path) = &args.snapshot {
+        snapshot::save(&app.data.rows(), path)
+            .map_err(|err| format!("couldn't write {}: {}", path, err))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::run;
+
+    #[test]
+    fn input_without_a_root_is_an_error() {
+        let mut rows: Vec<serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string("parth-doc-data.json").unwrap()).unwrap();
+        rows.retain(|row| row["file"]["id"] != row["file"]["parent"]);
+        //Named after the process too, so parallel runs don't trip over each other's files
+        let temp = |name: &str| {
+            std::env::temp_dir()
+                .join(format!(
+                    "space_inspector_test_{}.{}",
+                    std::process::id(),
+                    name
+                ))
+                .to_string_lossy()
+                .into_owned()
+        };
+        let (empty, orphans, svg) = (temp("empty.json"), temp("orphans.json"), temp("svg"));
+        std::fs::write(&empty, "[]").unwrap();
+        std::fs::write(&orphans, serde_json::to_string(&rows).unwrap()).unwrap();
+
+        for input in [&empty, &orphans] {
+            let args = ["--input", input, "--svg", &svg].map(str::to_owned);
+            let err = run(&args).unwrap_err();
+            assert!(err.contains("no root folder"), "{}", err);
+        }
+        assert!(!std::path::Path::new(&svg).exists());
+        std::fs::remove_file(empty).unwrap();
+        std::fs::remove_file(orphans).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use crate::data::Data;
+use crate::source::Source;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+//Counters the loading thread bumps as it goes, read by the window to draw progress
+#[derive(Default)]
+pub struct Progress {
+    pub rows_parsed: AtomicUsize,
+    //Stays at zero until parsing is done and the number of rows to add up is known
+    pub rows_total: AtomicUsize,
+    pub rows_aggregated: AtomicUsize,
+    pub cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn parsed(&self) {
+        self.rows_parsed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+//A source being opened and aggregated on a worker thread
+pub struct Loading {
+    pub path: String,
+    pub progress: Arc<Progress>,
+    receiver: Receiver<Result<(Source, Data), String>>,
+}
+
+impl Loading {
+    //`wake` is called once the result is ready so the window can swap it in
+    pub fn start(path: String, wake: impl Fn() + Send + 'static) -> Self {
+        let progress = Arc::new(Progress::default());
+        let (sender, receiver) = mpsc::channel();
+        let thread_path = path.clone();
+        let thread_progress = progress.clone();
+        thread::spawn(move || {
+            let result = Source::open(&thread_path).and_then(|source| {
+                let data = source.load_data(&thread_progress)?;
+                Ok((source, data))
+            });
+            let _ = sender.send(result);
+            wake();
+        });
+        Self {
+            path,
+            progress,
+            receiver,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    //The loaded source once the thread is done, or an error if it failed or was cancelled
+    pub fn poll(&self) -> Option<Result<(Source, Data), String>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("Loading stopped unexpectedly".to_owned())),
+        }
+    }
+
+    //A line describing how far along loading is, and the fraction done once that's known
+    pub fn status(&self) -> (String, Option<f32>) {
+        let parsed = self.progress.rows_parsed.load(Ordering::Relaxed);
+        let total = self.progress.rows_total.load(Ordering::Relaxed);
+        let aggregated = self.progress.rows_aggregated.load(Ordering::Relaxed);
+        if self.progress.is_cancelled() {
+            //A Lockbook listing can't be interrupted midway, so this may take until it's done
+            (
+                "Cancelling, waiting for the current step to finish".to_owned(),
+                None,
+            )
+        } else if total == 0 {
+            (
+                format!("Reading {}: {} rows parsed", self.path, parsed),
+                None,
+            )
+        } else {
+            (
+                format!("Adding up folder sizes: {} of {} rows", aggregated, total),
+                Some(aggregated as f32 / total as f32),
+            )
+        }
+    }
+}
+
+//A load nobody is waiting for anymore, like one in a closed tab, stops at the next row
+impl Drop for Loading {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
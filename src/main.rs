@@ -1,227 +1,783 @@
-use color_art;
-use colors_transform::{self, Color};
 use data::NodeLayer;
 use eframe::egui::{
-    self, menu, Align2, Color32, Context, FontFamily, FontId, Id, LayerId, Pos2, Rect, Rounding,
-    Sense, Stroke, TextWrapMode, Ui,
+    self, menu, Align2, Color32, EventFilter, FontFamily, FontId, Id, Key, LayerId, Modifiers,
+    Pos2, Rect, Rounding, Sense, Stroke, TextWrapMode, Ui, Visuals, WidgetInfo, WidgetType,
 };
 use lb_rs::model::usage::bytes_to_human;
 use lb_rs::Uuid;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{Receiver, TryRecvError};
 mod data;
+mod dialogs;
+mod export;
+mod headless;
+mod loading;
+mod palette;
+mod settings;
+mod snapshot;
+mod source;
+mod tabs;
 
-//Responsible for tracking on screen locations for folders
-#[derive(Debug)]
-struct DrawHelper {
-    id: Uuid,
-    starting_position: f32,
-}
+const DEFAULT_INPUT: &str = "parth-doc-data.json";
+const MENU_BAR_HEIGHT: f32 = 30.0;
+const SEARCH_ID: &str = "search";
+//How long a segment stays highlighted after a sync changed it
+const FLASH_SECONDS: f64 = 1.5;
 
-//Responsible for keeping colors consistent
-struct ColorHelper {
-    id: Uuid,
-    color: Color32,
+//Everything needed to draw one slice, whether on screen or in an export
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub id: Uuid,
+    pub name: String,
+    pub rect: Rect,
+    pub color: Color32,
+    pub layer: u64,
+    pub size: u64,
+    pub parent: Uuid,
+    pub is_folder: bool,
+    pub is_group: bool,
+    pub hidden_children: bool,
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if headless::wants_export(&args) {
+        if let Err(err) = headless::run(&args) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    //Only used on first launch, after that eframe restores the last window geometry
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([1500.0, 750.0]),
+        persist_window: true,
+        follow_system_theme: true,
         ..Default::default()
     };
     let _ = eframe::run_native(
         "Memory Viewer",
         options,
-        Box::new(|cc| Ok(Box::new(MyApp::init(cc.egui_ctx.clone())))),
+        Box::new(|cc| Ok(Box::new(tabs::Tabs::init(cc)))),
     );
 }
 
 struct MyApp {
     data: data::Data,
-    layer_height: f32,
+    settings: settings::Settings,
+    dark: bool,
+    palette_kind: palette::PaletteKind,
+    palette: palette::Palette,
+    custom_palette: Option<palette::CustomPalette>,
+    palette_status: Option<String>,
+    chrome: palette::Chrome,
+    chart_width: f32,
+    //None when there's no window to fit into, like in headless exports
+    chart_height: Option<f32>,
     paint_order: Vec<NodeLayer>,
-    colors: Vec<ColorHelper>,
+    colors: HashMap<Uuid, Color32>,
+    //Palette family of each top level folder, by id
+    families: HashMap<Uuid, usize>,
+    export_path: String,
+    export_status: Option<String>,
+    //Open while the search box is showing, keeps its text after jumping to a match
+    search: Option<String>,
+    search_matches: Vec<Uuid>,
+    search_hit: usize,
+    //Set when a folder was zoomed into from the keyboard, so focus moves onto its contents
+    focus_after_zoom: bool,
+    source: source::Source,
+    details: Option<Uuid>,
+    show_table: bool,
+    //Size report rows for the table window, emptied whenever the root changes
+    table: Vec<data::ReportRow>,
+    table_reveal: Option<Uuid>,
+    table_selected: Option<Uuid>,
+    renaming: Option<(Uuid, String)>,
+    moving: Option<(Uuid, String)>,
+    //Files waiting on the delete confirmation, empty when it's closed
+    deleting: Vec<Uuid>,
+    //Segments picked with ctrl or cmd click, for acting on many at once
+    selected: HashSet<Uuid>,
+    action_status: Option<String>,
+    //Fresh listings from a Lockbook account, started on the first frame
+    watcher: Option<source::Watcher>,
+    syncing: Option<Receiver<String>>,
+    sync_status: Option<String>,
+    //When each changed file was picked up, for fading its highlight out
+    flashes: HashMap<Uuid, f64>,
+    loading: Option<loading::Loading>,
+    //Why the last load didn't go through
+    load_status: Option<String>,
+    //What the File menu opens, a snapshot or a Lockbook data directory
+    open_path: String,
+    //The root saved last session, zoomed back into once the startup load finishes
+    saved_root: Option<Uuid>,
+    //Roots shown before and after the current one, for going back and forward
+    back: Vec<Uuid>,
+    forward: Vec<Uuid>,
 }
 
 impl MyApp {
-    fn init(_ctx: Context) -> Self {
-        //Will be accepting real data here soon
-        let data = data::Data::init(data::Data::from_file("parth-doc-data.json".to_owned()));
+    //A tab that starts loading its saved source straight away, unless it was left empty
+    fn open(
+        ctx: &egui::Context,
+        mut settings: settings::Settings,
+        system_theme: Option<eframe::Theme>,
+    ) -> Self {
+        if settings.source.is_empty() {
+            return Self::blank(settings, system_theme);
+        }
+        let mut saved_root = settings.root.take();
+        if !std::path::Path::new(&settings.source).exists() {
+            settings.source = DEFAULT_INPUT.to_owned();
+            saved_root = None;
+        }
+        let path = settings.source.clone();
+        let mut app = Self::blank(settings, system_theme);
+        app.saved_root = saved_root;
+        app.start_loading(ctx, path);
+        app
+    }
+
+    //A tab with nothing loaded, showing where to open something from
+    fn blank(settings: settings::Settings, system_theme: Option<eframe::Theme>) -> Self {
+        Self::new(
+            source::Source::Json(settings.source.clone()),
+            data::Data::init(vec![]),
+            settings,
+            system_theme,
+        )
+    }
+
+    fn pause_watching(&self, paused: bool) {
+        if let Some(watcher) = &self.watcher {
+            watcher.pause(paused);
+        }
+    }
+
+    //Named after the file or directory the tab shows, or is loading
+    fn tab_name(&self) -> String {
+        let path = match &self.loading {
+            Some(loading) => &loading.path,
+            None if self.data.all_files.is_empty() => return "New tab".to_owned(),
+            None => &self.settings.source,
+        };
+        std::path::Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.clone())
+    }
+
+    //Opens a source on a worker thread, whatever is loaded now stays until it's ready
+    fn start_loading(&mut self, ctx: &egui::Context, path: String) {
+        if let Some(loading) = &self.loading {
+            loading.cancel();
+        }
+        let ctx = ctx.clone();
+        self.loading = Some(loading::Loading::start(path, move || ctx.request_repaint()));
+    }
+
+    //Swaps in a finished load, or keeps what was there and says why it didn't work
+    fn finish_loading(&mut self) {
+        let Some(result) = self.loading.as_ref().and_then(|loading| loading.poll()) else {
+            return;
+        };
+        let path = self.loading.take().unwrap().path.clone();
+        //Only the first load is the one the saved root belongs to, whether or not it worked
+        let saved_root = self.saved_root.take();
+        match result {
+            Ok((source, data)) => {
+                self.source = source;
+                self.data = data;
+                self.open_path = path.clone();
+                self.settings.source = path;
+                //Anything still tied to the old source goes, the rest is pruned to what the new one has
+                self.watcher = None;
+                self.syncing = None;
+                self.sync_status = None;
+                self.action_status = None;
+                self.load_status = None;
+                self.flashes.clear();
+                self.colors.clear();
+                self.families.clear();
+                self.table_selected = None;
+                self.back.clear();
+                self.forward.clear();
+                if let Some(query) = &self.search {
+                    self.search_matches = self.data.find(query);
+                    self.search_hit = 0;
+                }
+                self.forget_missing();
+                if let Some(root) = saved_root {
+                    if self
+                        .data
+                        .all_files
+                        .get(&root)
+                        .is_some_and(|row| row.file.is_folder())
+                    {
+                        self.change_root(root);
+                    }
+                }
+            }
+            Err(err) => self.load_status = Some(err),
+        }
+    }
+
+    //Shown instead of the chart while loading, or when nothing could be loaded
+    fn loading_screen(&mut self, ctx: &egui::Context, tabs: &mut tabs::TabBar) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| tabs.show(ui));
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() / 3.0);
+                if let Some(loading) = &self.loading {
+                    let (status, fraction) = loading.status();
+                    ui.label(status);
+                    match fraction {
+                        Some(fraction) => {
+                            ui.add(
+                                egui::ProgressBar::new(fraction)
+                                    .desired_width(300.0)
+                                    .show_percentage(),
+                            );
+                        }
+                        None => {
+                            ui.spinner();
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        loading.cancel();
+                    }
+                    ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                } else {
+                    if let Some(status) = &self.load_status {
+                        ui.label(status);
+                        if ui.button("Try again").clicked() {
+                            self.start_loading(ctx, self.settings.source.clone());
+                        }
+                        ui.add_space(20.0);
+                    }
+                    ui.label(
+                        "Open a snapshot or Lockbook data directory, or drop one onto the window:",
+                    );
+                    self.open_field(ctx, ui);
+                }
+            });
+        });
+    }
+
+    //Native pickers for a snapshot or a Lockbook data directory, with a path box as a fallback for
+    //when no file dialog is available. Shared by the File menu and the loading screen
+    fn open_field(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let start = std::path::Path::new(&self.open_path)
+            .parent()
+            .map(|parent| parent.to_path_buf())
+            .unwrap_or_default();
+        let mut picked = None;
+        if ui.button("Open snapshot…").clicked() {
+            picked = rfd::FileDialog::new()
+                .set_directory(&start)
+                .add_filter("Snapshots", &["json", "gz", "zst", "snap"])
+                .add_filter("All files", &["*"])
+                .pick_file();
+            ui.close_menu();
+        }
+        if ui.button("Open Lockbook data directory…").clicked() {
+            picked = rfd::FileDialog::new().set_directory(&start).pick_folder();
+            ui.close_menu();
+        }
+        if let Some(path) = picked {
+            self.open_path = path.to_string_lossy().into_owned();
+            self.start_loading(ctx, self.open_path.clone());
+        }
+        ui.label("Or type a path:");
+        ui.horizontal(|ui| {
+            let field = ui.text_edit_singleline(&mut self.open_path);
+            let entered = field.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+            if ui.button("Open").clicked() || entered {
+                self.start_loading(ctx, self.open_path.clone());
+                ui.close_menu();
+            }
+        });
+    }
+
+    //Opens the first file dropped onto the window, and hints where to drop while one is dragged over
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let (hovering, dropped) = ctx.input(|input| {
+            (
+                !input.raw.hovered_files.is_empty(),
+                input
+                    .raw
+                    .dropped_files
+                    .iter()
+                    .find_map(|file| file.path.clone()),
+            )
+        });
+        if let Some(path) = dropped {
+            self.start_loading(ctx, path.to_string_lossy().into_owned());
+        }
+        if hovering {
+            let painter = ctx.layer_painter(LayerId::new(egui::Order::Foreground, Id::new("drop")));
+            let screen = ctx.screen_rect();
+            painter.rect_filled(screen, 0.0, Color32::from_black_alpha(160));
+            painter.text(
+                screen.center(),
+                Align2::CENTER_CENTER,
+                "Drop to open",
+                FontId::proportional(24.0),
+                Color32::WHITE,
+            );
+        }
+    }
 
+    //`system_theme` is what the platform prefers, if it says, for settings that follow it
+    fn new(
+        source: source::Source,
+        data: data::Data,
+        settings: settings::Settings,
+        system_theme: Option<eframe::Theme>,
+    ) -> Self {
+        let dark = settings.theme.is_dark(system_theme);
+        let mut palette_status = None;
+        let custom_palette = match settings.custom_palette.as_str() {
+            "" => None,
+            path => palette::CustomPalette::from_file(path)
+                .map_err(|err| palette_status = Some(err))
+                .ok(),
+        };
         Self {
-            data: data,
+            open_path: settings.source.clone(),
+            palette_kind: settings.palette,
+            palette: palette::Palette::new(settings.palette, dark, custom_palette.as_ref()),
+            chrome: palette::Chrome::new(dark),
+            chart_width: 1500.0,
+            chart_height: None,
+            custom_palette,
+            palette_status,
+            data,
+            settings,
+            dark,
             paint_order: vec![],
-            layer_height: 50.0,
-            colors: vec![],
+            colors: HashMap::new(),
+            families: HashMap::new(),
+            export_path: "space_inspector".to_owned(),
+            export_status: None,
+            search: None,
+            search_matches: vec![],
+            search_hit: 0,
+            focus_after_zoom: false,
+            source,
+            details: None,
+            show_table: false,
+            table: vec![],
+            table_reveal: None,
+            table_selected: None,
+            renaming: None,
+            moving: None,
+            deleting: vec![],
+            selected: HashSet::new(),
+            action_status: None,
+            watcher: None,
+            syncing: None,
+            sync_status: None,
+            flashes: HashMap::new(),
+            loading: None,
+            load_status: None,
+            saved_root: None,
+            back: vec![],
+            forward: vec![],
         }
     }
 
     pub fn change_root(&mut self, new_root: Uuid) {
-        self.data.current_root = new_root;
-        self.paint_order = vec![];
+        if new_root != self.data.current_root {
+            self.back.push(self.data.current_root);
+            self.forward.clear();
+        }
+        self.show_root(new_root);
     }
 
     pub fn reset_root(&mut self) {
-        self.data.current_root = self.data.overall_root;
+        self.change_root(self.data.overall_root);
+    }
+
+    pub fn go_back(&mut self) {
+        if let Some(root) = self.back.pop() {
+            self.forward.push(self.data.current_root);
+            self.show_root(root);
+        }
+    }
+
+    pub fn go_forward(&mut self) {
+        if let Some(root) = self.forward.pop() {
+            self.back.push(self.data.current_root);
+            self.show_root(root);
+        }
+    }
+
+    //Switches the root without touching the history
+    fn show_root(&mut self, root: Uuid) {
+        self.data.current_root = root;
+        self.selected.clear();
+        self.paint_order = vec![];
+    }
+
+    //Reads everything from the source again and applies only what changed, handing back those ids
+    fn reload(&mut self) -> Result<HashSet<Uuid>, String> {
+        let rows = self.source.load()?;
+        let changed = if rows.iter().any(|row| row.file.id == self.data.overall_root) {
+            self.data.update(rows)
+        } else {
+            self.data = data::Data::init(rows);
+            HashSet::new()
+        };
         self.paint_order = vec![];
+        Ok(changed)
     }
 
-    pub fn get_color(&self, curr_id: Uuid, mut layer: usize, mut child_number: usize) -> Color32 {
-        let big_table = vec![
-            //red
-            [
-                Color32::from_rgb(128, 15, 47),
-                Color32::from_rgb(164, 19, 60),
-                Color32::from_rgb(201, 24, 74),
-                Color32::from_rgb(255, 77, 109),
-                Color32::from_rgb(255, 117, 143),
-                Color32::from_rgb(255, 143, 163),
-            ],
-            //green
-            [
-                Color32::from_rgb(27, 67, 50),
-                Color32::from_rgb(45, 106, 79),
-                Color32::from_rgb(64, 145, 108),
-                Color32::from_rgb(82, 183, 136),
-                Color32::from_rgb(116, 198, 157),
-                Color32::from_rgb(116, 198, 157),
-            ],
-            //blue
-            [
-                Color32::from_rgb(2, 62, 138),
-                Color32::from_rgb(0, 119, 182),
-                Color32::from_rgb(0, 150, 199),
-                Color32::from_rgb(0, 180, 216),
-                Color32::from_rgb(72, 202, 228),
-                Color32::from_rgb(144, 224, 239),
-            ],
-        ];
-        if layer == 1 {
-            if child_number > 2 {
-                child_number = child_number % 3;
+    //Applies whatever the watcher and a running sync have sent since the last frame
+    fn poll_source(&mut self, ctx: &egui::Context) {
+        let now = ctx.input(|input| input.time);
+        if self.watcher.is_none() && self.source.is_live() {
+            let ctx = ctx.clone();
+            self.watcher = self.source.watch(move || ctx.request_repaint());
+        }
+        let listings: Vec<_> = self
+            .watcher
+            .as_ref()
+            .map(|watcher| watcher.listings().collect())
+            .unwrap_or_default();
+        for listing in listings {
+            match listing {
+                Ok(rows) => {
+                    for id in self.data.update(rows) {
+                        self.flashes.insert(id, now);
+                    }
+                    self.forget_missing();
+                }
+                Err(err) => self.action_status = Some(err),
             }
-            return big_table[child_number][0];
         }
 
-        let parent_color = self
-            .colors
-            .iter()
-            .find(|item| item.id == self.data.all_files.get(&curr_id).unwrap().file.parent)
-            .unwrap()
-            .color;
+        if let Some(syncing) = &self.syncing {
+            loop {
+                match syncing.try_recv() {
+                    Ok(message) => self.sync_status = Some(message),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.syncing = None;
+                        break;
+                    }
+                }
+            }
+        }
 
-        let parent_type = big_table
-            .iter()
-            .enumerate()
-            .find_map(|(row_index, row)| {
-                row.iter()
-                    .position(|&x| x == parent_color)
-                    .map(|col_index| (row_index, col_index))
-            })
-            .unwrap()
-            .0;
+        self.flashes.retain(|_, start| now - *start < FLASH_SECONDS);
+        if !self.flashes.is_empty() {
+            ctx.request_repaint();
+        }
+    }
 
-        layer -= 1;
+    //Moves the root up one folder, handing back the old root so it can take focus
+    pub fn zoom_out(&mut self) -> Option<Uuid> {
+        let old_root = self.data.current_root;
+        if old_root == self.data.overall_root {
+            return None;
+        }
+        self.change_root(self.data.all_files.get(&old_root).unwrap().file.parent);
+        Some(old_root)
+    }
+
+    //Zooms to the file's folder unless it's already on screen, then gives it keyboard focus
+    fn reveal(&mut self, ctx: &egui::Context, id: Uuid) {
+        if !self.paint_order.iter().any(|item| item.id == id) {
+            let parent = self.data.all_files.get(&id).unwrap().file.parent;
+            self.change_root(parent);
+            self.data.expanded_groups.insert(parent);
+        }
+        ctx.memory_mut(|memory| memory.request_focus(Id::new(id)));
+    }
 
-        if layer > 5 {
-            layer = layer % 6;
+    //Steps through the search matches, one per Enter
+    fn next_search_hit(&mut self, ctx: &egui::Context) {
+        if self.search_matches.is_empty() {
+            return;
         }
+        let id = self.search_matches[self.search_hit % self.search_matches.len()];
+        self.search_hit += 1;
+        self.reveal(ctx, id);
+    }
 
-        return big_table[parent_type][layer];
+    //Keys that work while the chart has focus, or nothing does, so typing in a text box is left alone
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        if let Some(focused) = ctx.memory(|memory| memory.focused()) {
+            if !self
+                .paint_order
+                .iter()
+                .any(|item| Id::new(item.id) == focused)
+            {
+                return;
+            }
+        }
+        if ctx.input_mut(|input| input.consume_key(Modifiers::NONE, Key::Slash)) {
+            //The slash would otherwise be typed into the box it just opened
+            ctx.input_mut(|input| {
+                input
+                    .events
+                    .retain(|event| !matches!(event, egui::Event::Text(text) if text == "/"))
+            });
+            self.search.get_or_insert_with(String::new);
+            ctx.memory_mut(|memory| memory.request_focus(Id::new(SEARCH_ID)));
+        }
+        if !self.selected.is_empty()
+            && ctx.input_mut(|input| input.consume_key(Modifiers::NONE, Key::Delete))
+        {
+            self.deleting = self.selected.iter().copied().collect();
+        }
+        if ctx.input_mut(|input| input.consume_key(Modifiers::ALT, Key::ArrowLeft)) {
+            self.go_back();
+        }
+        if ctx.input_mut(|input| input.consume_key(Modifiers::ALT, Key::ArrowRight)) {
+            self.go_forward();
+        }
+        if ctx.input_mut(|input| input.consume_key(Modifiers::NONE, Key::Backspace)) {
+            if let Some(old_root) = self.zoom_out() {
+                ctx.memory_mut(|memory| memory.request_focus(Id::new(old_root)));
+            }
+        }
     }
 
-    pub fn follow_paint_order(&mut self, ui: &mut Ui, root_anchor: Rect) -> Option<Uuid> {
-        let mut root_status: Option<Uuid> = None;
-        let mut current_layer = 0;
-        let mut current_position = 0.0;
-        let mut general_counter = 0;
-        let mut child_number = 1;
-        let mut visited_folders: Vec<DrawHelper> = vec![];
-        let mut current_parent = DrawHelper {
-            id: self.data.current_root,
-            starting_position: 0.0,
-        };
-        for item in &self.paint_order {
-            let item_filerow = self.data.all_files.get(&item.id).unwrap();
+    //Swaps palettes when the resolved theme or chosen palette changes
+    pub fn apply_palette(&mut self, ctx: &egui::Context, dark: bool) {
+        if dark != ctx.style().visuals.dark_mode {
+            ctx.set_visuals(if dark {
+                Visuals::dark()
+            } else {
+                Visuals::light()
+            });
+        }
+        if dark == self.dark && self.settings.palette == self.palette_kind {
+            return;
+        }
+        self.dark = dark;
+        self.rebuild_palette();
+    }
 
-            if current_layer != item.layer {
-                current_position = 0.0;
-                current_layer = item.layer;
+    pub fn rebuild_palette(&mut self) {
+        self.palette_kind = self.settings.palette;
+        self.palette =
+            palette::Palette::new(self.palette_kind, self.dark, self.custom_palette.as_ref());
+        self.chrome = palette::Chrome::new(self.dark);
+        self.assign_colors();
+    }
+
+    pub fn load_custom_palette(&mut self) {
+        match palette::CustomPalette::from_file(&self.settings.custom_palette) {
+            Ok(custom_palette) => {
+                self.custom_palette = Some(custom_palette);
+                self.settings.palette = palette::PaletteKind::Custom;
+                self.rebuild_palette();
+                self.palette_status = Some("Loaded custom palette".to_owned());
             }
+            Err(err) => self.palette_status = Some(err),
+        }
+    }
 
-            if item_filerow.file.parent != current_parent.id {
-                child_number = 1;
-                current_position = visited_folders
-                    .iter()
-                    .find(|parent| parent.id == item_filerow.file.parent)
-                    .unwrap()
-                    .starting_position;
-                current_parent = DrawHelper {
-                    id: self
-                        .data
-                        .all_files
-                        .get(&item.id)
-                        .unwrap()
-                        .file
-                        .parent
-                        .clone(),
-                    starting_position: current_position.clone(),
-                };
+    //Hue comes from the ancestor under the overall root and shade from depth below it,
+    //so a folder keeps its color no matter which of its ancestors is being viewed
+    pub fn assign_colors(&mut self) {
+        //A top level folder keeps the family it was first given, so deleting, moving or syncing files
+        //doesn't shift everyone else's color. New ones take the first family nobody is using
+        let order = self.data.get_top_level_order();
+        let present: HashSet<Uuid> = order.iter().copied().collect();
+        self.families.retain(|id, _| present.contains(id));
+        for id in order {
+            if !self.families.contains_key(&id) {
+                let used: HashSet<usize> = self.families.values().copied().collect();
+                let family = (0..).find(|family| !used.contains(family)).unwrap();
+                self.families.insert(id, family);
             }
-            let painter = ui.painter();
+        }
+        let families = &self.families;
+        self.colors = HashMap::new();
+        for item in &self.paint_order {
+            let (family, depth) = match self.data.get_top_level(&item.representative()) {
+                Some((top_level, depth)) => (families.get(&top_level).copied().unwrap_or(0), depth),
+                None => (0, item.layer),
+            };
+            self.colors.insert(
+                item.id,
+                self.palette.color(family, depth.saturating_sub(1) as usize),
+            );
+        }
+    }
+
+    //Turns the small item setting into bytes for the current root and chart width
+    pub fn group_threshold(&self) -> u64 {
+        let root_size = self.data.get_size(&self.data.current_root) as f64;
+        let amount = self.settings.group_amount.max(0.0) as f64;
+        match self.settings.group_unit {
+            settings::GroupUnit::Bytes => amount as u64,
+            settings::GroupUnit::Percent => (root_size * amount / 100.0) as u64,
+            settings::GroupUnit::Pixels => {
+                (root_size * amount / self.chart_width.max(1.0) as f64) as u64
+            }
+        }
+    }
+
+    //How many layers fit between the menu bar and the root strip
+    pub fn fitting_depth(&self) -> Option<u64> {
+        if !self.settings.fit_depth_to_window {
+            return None;
+        }
+        let height = self.chart_height? - MENU_BAR_HEIGHT - export::ROOT_BAR_HEIGHT;
+        Some(((height / self.settings.layer_height).floor() as u64).max(1))
+    }
+
+    //Rebuilds the paint order and its colors after anything marked them stale
+    pub fn refresh_paint_order(&mut self) {
+        let group_below = self.group_threshold();
+        let max_depth = self.fitting_depth();
+        if group_below != self.data.group_below
+            || max_depth != self.data.max_depth
+            || self.settings.sort != self.data.sort
+        {
+            self.data.group_below = group_below;
+            self.data.max_depth = max_depth;
+            self.data.sort = self.settings.sort;
+            self.paint_order = vec![];
+        }
+        if self.paint_order.is_empty() {
+            self.table = vec![];
+            self.paint_order = data::Data::get_paint_order(&self.data);
+            self.assign_colors();
+        }
+    }
+
+    //Works out where every item in the paint order goes relative to the root anchor
+    pub fn layout(&mut self, root_anchor: Rect) -> Vec<Segment> {
+        let mut segments = vec![];
+        //Both edges come from byte counts so neighbours share an edge exactly and nothing rounds away
+        let bytes_per_point =
+            self.data.get_size(&self.data.current_root).max(1) as f64 / root_anchor.width() as f64;
+        let edge = |bytes: u64| root_anchor.min.x + (bytes as f64 / bytes_per_point) as f32;
+        for item in &self.paint_order {
+            let item_filerow = self.data.all_files.get(&item.representative()).unwrap();
+            let is_group = !item.grouped.is_empty();
+            let current_layer = item.layer;
+
             let paint_rect = Rect {
                 min: Pos2 {
-                    x: current_position,
-                    y: root_anchor.min.y - (current_layer as f32) * self.layer_height,
+                    x: edge(item.offset),
+                    y: root_anchor.min.y - (current_layer as f32) * self.settings.layer_height,
                 },
                 max: Pos2 {
-                    x: current_position + (item.portion * root_anchor.max.x),
-                    y: root_anchor.min.y - ((current_layer - 1) as f32) * self.layer_height,
+                    x: edge(item.offset + item.size),
+                    y: root_anchor.min.y
+                        - ((current_layer - 1) as f32) * self.settings.layer_height,
                 },
             };
 
             let current_color = self
                 .colors
-                .iter()
-                .find_map(|element| {
-                    if element.id == item.id {
-                        return Some(element.color);
-                    } else {
-                        return None;
-                    }
-                })
-                .unwrap_or(MyApp::get_color(
-                    &self,
-                    item.id,
-                    current_layer as usize,
-                    child_number - 1,
-                ));
+                .get(&item.id)
+                .copied()
+                .unwrap_or(self.palette.color(0, 0));
+
+            segments.push(Segment {
+                id: item.id,
+                name: item.name.clone(),
+                rect: paint_rect,
+                color: current_color,
+                layer: current_layer,
+                size: item.size,
+                parent: item_filerow.file.parent,
+                is_folder: !is_group && item_filerow.file.is_folder(),
+                is_group,
+                hidden_children: item.hidden_children,
+            });
+        }
+        segments
+    }
+
+    //Lays the current view out on a canvas of the given width, independent of the window
+    pub fn chart(&mut self, width: f32) -> export::Chart {
+        self.chart_width = width;
+        self.refresh_paint_order();
+        let max_layer = self
+            .paint_order
+            .iter()
+            .map(|item| item.layer)
+            .max()
+            .unwrap_or(0);
+        let top_level = self
+            .paint_order
+            .iter()
+            .filter(|item| item.layer == 1)
+            .count();
+        let height = export::legend_height(top_level)
+            + max_layer as f32 * self.settings.layer_height
+            + export::ROOT_BAR_HEIGHT;
+        let root_bar = Rect {
+            min: Pos2 {
+                x: 0.0,
+                y: height - export::ROOT_BAR_HEIGHT,
+            },
+            max: Pos2 {
+                x: width,
+                y: height,
+            },
+        };
+        export::Chart {
+            width,
+            height,
+            root_bar,
+            root_name: self
+                .data
+                .all_files
+                .get(&self.data.current_root)
+                .unwrap()
+                .file
+                .name
+                .clone(),
+            root_size: *self.data.folder_sizes.get(&self.data.current_root).unwrap(),
+            chrome: self.chrome,
+            segments: self.layout(root_bar),
+        }
+    }
+
+    fn export(&mut self, width: f32, extension: &str) {
+        let path = format!("{}.{}", self.export_path, extension);
+        let result = match extension {
+            "svg" => export::save_svg(&self.chart(width), &path).map_err(|err| err.to_string()),
+            "png" => export::save_png(&self.chart(width), &path).map_err(|err| err.to_string()),
+            "csv" => {
+                export::save_csv(&self.data.get_report(), &path).map_err(|err| err.to_string())
+            }
+            _ => export::save_json(&self.data.get_report(), &path).map_err(|err| err.to_string()),
+        };
+        self.export_status = Some(match result {
+            Ok(()) => format!("Saved {}", path),
+            Err(err) => format!("Couldn't save {}: {}", path, err),
+        });
+    }
+
+    pub fn follow_paint_order(&mut self, ui: &mut Ui, root_anchor: Rect) -> Option<Uuid> {
+        let mut root_status: Option<Uuid> = None;
+        let mut expanded_group: Option<Uuid> = None;
+        let mut action: Option<dialogs::SegmentAction> = None;
+        let mut dropped: Option<(Uuid, Uuid)> = None;
+        let segments = self.layout(root_anchor);
+        let mut focused: Option<&Segment> = None;
+        for segment in &segments {
+            let painter = ui.painter();
+            let paint_rect = segment.rect;
 
             //Folder text logic
-            let tab_intel: egui::WidgetText = egui::RichText::new(item.name.clone())
+            let tab_intel: egui::WidgetText = egui::RichText::new(segment.name.clone())
                 .font(egui::FontId::monospace(12.0))
-                .color({
-                    let hsl_color = colors_transform::Rgb::from(
-                        current_color.r().into(),
-                        current_color.g().into(),
-                        current_color.b().into(),
-                    )
-                    .to_hsl();
-                    let mut luminance = 0.5;
-                    if hsl_color.get_lightness() > 50.0 {
-                        luminance = (hsl_color.get_lightness() - 50.0) / 100.0;
-                    } else {
-                        luminance = (hsl_color.get_lightness() + 50.0) / 100.0;
-                    }
-                    Color32::from_hex(
-                        &(color_art::color!(
-                            HSL,
-                            hsl_color.get_hue(),
-                            hsl_color.get_saturation() / 100.0,
-                            luminance
-                        ))
-                        .hex(),
-                    )
-                    .unwrap_or(Color32::DEBUG_COLOR)
-                })
+                .color(palette::label_color(segment.color))
                 .into();
             let tab_intel_galley = tab_intel.into_galley(
                 ui,
@@ -240,10 +796,10 @@ impl MyApp {
             painter.clone().rect(
                 paint_rect,
                 Rounding::ZERO,
-                current_color,
+                segment.color,
                 Stroke {
                     width: 0.5,
-                    color: Color32::BLACK,
+                    color: self.chrome.border,
                 },
             );
 
@@ -255,64 +811,267 @@ impl MyApp {
                 );
             }
 
-            let display_size = if item_filerow.file.is_folder() {
-                bytes_to_human(self.data.folder_sizes.get(&item.id).unwrap().clone())
+            if let Some(marker) = export::hidden_marker(segment) {
+                ui.painter().add(egui::Shape::convex_polygon(
+                    marker.to_vec(),
+                    palette::label_color(segment.color),
+                    Stroke::NONE,
+                ));
+            }
+
+            let mut display_size = bytes_to_human(segment.size);
+            if segment.hidden_children {
+                display_size += "\nClick to see what's inside";
+            }
+
+            let sense = if segment.is_group {
+                Sense::click()
             } else {
-                bytes_to_human(item_filerow.size)
+                Sense::click_and_drag()
             };
+            let response = ui.interact(paint_rect, Id::new(segment.id), sense);
+            if !segment.is_group {
+                response.dnd_set_drag_payload(segment.id);
+            }
+            if segment.is_folder {
+                if let Some(dragged) = response.dnd_hover_payload::<Uuid>() {
+                    if self.data.can_move(&dragged, &segment.id) {
+                        ui.painter().rect_stroke(
+                            paint_rect.shrink(1.0),
+                            Rounding::ZERO,
+                            Stroke {
+                                width: 3.0,
+                                color: self.chrome.text,
+                            },
+                        );
+                    }
+                }
+                if let Some(dragged) = response.dnd_release_payload::<Uuid>() {
+                    dropped = Some((*dragged, segment.id));
+                }
+            }
+            response.widget_info(|| {
+                let typ = if segment.is_folder || segment.is_group {
+                    WidgetType::Button
+                } else {
+                    WidgetType::Label
+                };
+                WidgetInfo::labeled(typ, true, self.describe(segment))
+            });
 
-            let response = ui.interact(paint_rect, Id::new(general_counter), Sense::click());
+            if self.search_matches.contains(&segment.id) {
+                ui.painter().rect_stroke(
+                    paint_rect.shrink(1.0),
+                    Rounding::ZERO,
+                    Stroke {
+                        width: 2.0,
+                        color: self.chrome.text,
+                    },
+                );
+            }
+            if response.has_focus() {
+                ui.painter().rect_stroke(
+                    paint_rect.shrink(1.0),
+                    Rounding::ZERO,
+                    ui.visuals().selection.stroke,
+                );
+                focused = Some(segment);
+            }
 
-            if response.clicked() {
-                if item_filerow.file.is_folder() {
-                    root_status = Some(item.id.clone());
+            if let Some(start) = self.flashes.get(&segment.id) {
+                let fade = 1.0 - (ui.input(|input| input.time) - start) / FLASH_SECONDS;
+                ui.painter().rect_filled(
+                    paint_rect,
+                    Rounding::ZERO,
+                    self.chrome.text.gamma_multiply(fade.max(0.0) as f32 * 0.6),
+                );
+            }
+            if self.selected.contains(&segment.id) {
+                ui.painter().rect_filled(
+                    paint_rect,
+                    Rounding::ZERO,
+                    ui.visuals().selection.bg_fill.gamma_multiply(0.5),
+                );
+            }
+
+            let selecting = ui.input(|input| input.modifiers.command);
+            if response.clicked() && selecting && !segment.is_group {
+                if !self.selected.remove(&segment.id) {
+                    self.selected.insert(segment.id);
                 }
+            } else if response.clicked() && segment.is_folder {
+                root_status = Some(segment.id);
+                self.focus_after_zoom = response.has_focus();
+            }
+            if response.clicked() && !selecting && segment.is_group {
+                expanded_group = Some(segment.parent);
             }
 
-            response.on_hover_text(
-                "Name:\n".to_owned()
-                    + &self
-                        .data
-                        .all_files
-                        .get(&item.id)
-                        .unwrap()
-                        .file
-                        .name
-                        .to_string()
-                    + "\nSize:\n"
-                    + &display_size,
-            );
+            response.context_menu(|ui| {
+                if let Some(chosen) = self.segment_menu(ui, segment) {
+                    action = Some(chosen);
+                }
+            });
 
-            if item_filerow.file.is_folder() {
-                visited_folders.push(DrawHelper {
-                    id: item.id.clone(),
-                    starting_position: current_position.clone(),
-                });
+            response
+                .on_hover_text("Name:\n".to_owned() + &segment.name + "\nSize:\n" + &display_size);
+        }
+        if let Some(segment) = focused {
+            if let Some(target) = MyApp::keyboard_target(ui, &segments, segment) {
+                ui.memory_mut(|memory| memory.request_focus(Id::new(target)));
             }
-            self.colors.push(ColorHelper {
-                id: item.id,
-                color: current_color,
-            });
+        }
+        if let Some(parent) = expanded_group {
+            self.data.expanded_groups.insert(parent);
+            self.paint_order = vec![];
+        }
+        if let Some(action) = action {
+            self.apply_action(action);
+        }
+        if let Some(dragged) = egui::DragAndDrop::payload::<Uuid>(ui.ctx()) {
+            MyApp::drag_label(ui, &segments, *dragged);
+        }
+        if let Some((dragged, folder)) = dropped {
+            //Dragging one of the selected segments brings the rest of the selection along
+            let ids: Vec<Uuid> = if self.selected.contains(&dragged) {
+                self.selected.iter().copied().collect()
+            } else {
+                vec![dragged]
+            };
+            self.action_status = None;
+            self.move_files(&ids, folder);
+        }
+        root_status
+    }
 
-            current_position += item.portion * root_anchor.max.x;
-            child_number += 1;
-            general_counter += 1;
+    //What a screen reader announces for a segment: its name, kind, size and share of the root
+    fn describe(&self, segment: &Segment) -> String {
+        let root_size = self.data.get_size(&self.data.current_root).max(1);
+        let kind = if segment.is_group {
+            "group"
+        } else if segment.is_folder {
+            "folder"
+        } else {
+            "file"
+        };
+        let mut description = format!(
+            "{}, {}, {}, {:.1}% of {}",
+            segment.name,
+            kind,
+            bytes_to_human(segment.size),
+            segment.size as f64 / root_size as f64 * 100.0,
+            self.data
+                .all_files
+                .get(&self.data.current_root)
+                .unwrap()
+                .file
+                .name
+        );
+        if segment.hidden_children {
+            description += ", more inside";
         }
-        return root_status;
+        description
     }
-}
 
-impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            //Start of pre ui checks
-            if self.paint_order.is_empty() {
-                self.paint_order = data::Data::get_paint_order(&self.data);
+    //Follows the pointer with the name of whatever is being dragged
+    fn drag_label(ui: &Ui, segments: &[Segment], dragged: Uuid) {
+        let (Some(pointer), Some(segment)) = (
+            ui.ctx().pointer_interact_pos(),
+            segments.iter().find(|segment| segment.id == dragged),
+        ) else {
+            return;
+        };
+        let painter = ui.ctx().layer_painter(LayerId {
+            order: egui::Order::Tooltip,
+            id: Id::new("drag"),
+        });
+        let color = ui.visuals().strong_text_color();
+        let galley = painter.layout_no_wrap(segment.name.clone(), FontId::monospace(12.0), color);
+        let label = Rect::from_min_size(pointer + egui::vec2(12.0, 12.0), galley.size());
+        painter.rect_filled(label.expand(3.0), 2.0, ui.visuals().extreme_bg_color);
+        painter.galley(label.min, galley, color);
+    }
+
+    //Arrow keys walk the tree: left and right between siblings, up into children, down to the parent
+    fn keyboard_target(ui: &mut Ui, segments: &[Segment], focused: &Segment) -> Option<Uuid> {
+        //Keeps egui from moving focus by screen position while a segment holds it
+        ui.memory_mut(|memory| {
+            memory.set_focus_lock_filter(
+                Id::new(focused.id),
+                EventFilter {
+                    horizontal_arrows: true,
+                    vertical_arrows: true,
+                    ..Default::default()
+                },
+            )
+        });
+        let siblings: Vec<&Segment> = segments
+            .iter()
+            .filter(|segment| segment.layer == focused.layer && segment.parent == focused.parent)
+            .collect();
+        let position = siblings
+            .iter()
+            .position(|segment| segment.id == focused.id)?;
+        ui.input(|input| {
+            if input.key_pressed(Key::ArrowLeft) {
+                siblings
+                    .get(position.checked_sub(1)?)
+                    .map(|segment| segment.id)
+            } else if input.key_pressed(Key::ArrowRight) {
+                siblings.get(position + 1).map(|segment| segment.id)
+            } else if input.key_pressed(Key::ArrowUp) {
+                segments
+                    .iter()
+                    .find(|segment| {
+                        segment.parent == focused.id && segment.layer == focused.layer + 1
+                    })
+                    .map(|segment| segment.id)
+            } else if input.key_pressed(Key::ArrowDown) {
+                segments
+                    .iter()
+                    .find(|segment| segment.id == focused.parent)
+                    .map(|segment| segment.id)
+            } else {
+                None
             }
+        })
+    }
+}
+
+impl MyApp {
+    //The settings to remember for this tab, including the root it's showing, or the one it will
+    //show once its startup load is done
+    fn saved_settings(&self) -> settings::Settings {
+        let mut settings = self.settings.clone();
+        settings.root = if self.data.all_files.is_empty() {
+            self.saved_root
+        } else {
+            Some(self.data.current_root)
+        };
+        settings
+    }
 
+    //Draws a frame of this tab, with `tabs` shown at the start of its menu bar
+    fn show(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame, tabs: &mut tabs::TabBar) {
+        let dark = self.settings.theme.is_dark(frame.info().system_theme);
+        self.apply_palette(ctx, dark);
+        self.handle_dropped_files(ctx);
+        self.finish_loading();
+        if self.loading.is_some() || self.data.all_files.is_empty() {
+            self.loading_screen(ctx, tabs);
+            return;
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
             //Allows for dynamic window
             let window_size = ctx.input(|i: &egui::InputState| i.screen_rect());
 
+            //Start of pre ui checks
+            self.chart_width = window_size.width();
+            self.chart_height = Some(window_size.height());
+            self.handle_shortcuts(ctx);
+            self.poll_source(ctx);
+            self.refresh_paint_order();
+
             //Top buttons
 
             ui.with_layer_id(
@@ -322,14 +1081,165 @@ impl eframe::App for MyApp {
                 },
                 |ui| {
                     menu::bar(ui, |ui| {
+                        tabs.show(ui);
+
+                        ui.menu_button("File", |ui| {
+                            self.open_field(ctx, ui);
+                        });
+
+                        if ui
+                            .add_enabled(!self.back.is_empty(), egui::Button::new("◀"))
+                            .on_hover_text("Back (Alt+Left)")
+                            .clicked()
+                        {
+                            self.go_back();
+                        }
+                        if ui
+                            .add_enabled(!self.forward.is_empty(), egui::Button::new("▶"))
+                            .on_hover_text("Forward (Alt+Right)")
+                            .clicked()
+                        {
+                            self.go_forward();
+                        }
+
                         if ui.button("Reset Root").clicked() {
                             self.reset_root();
-                            self.paint_order = vec![];
                         }
 
                         ui.menu_button("Layer Size", |ui| {
-                            ui.add(egui::Slider::new(&mut self.layer_height, 1.0..=100.0));
+                            ui.add(egui::Slider::new(
+                                &mut self.settings.layer_height,
+                                1.0..=100.0,
+                            ));
+                            ui.checkbox(
+                                &mut self.settings.fit_depth_to_window,
+                                "Only build layers that fit in the window",
+                            );
+                        });
+
+                        ui.menu_button("Small Items", |ui| {
+                            ui.label("Group siblings smaller than");
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::DragValue::new(&mut self.settings.group_amount)
+                                        .speed(0.1)
+                                        .range(0.0..=f32::MAX),
+                                );
+                                for unit in settings::GroupUnit::ALL {
+                                    ui.radio_value(
+                                        &mut self.settings.group_unit,
+                                        unit,
+                                        unit.name(),
+                                    );
+                                }
+                            });
+                            if ui.button("Collapse opened groups").clicked() {
+                                self.data.expanded_groups.clear();
+                                self.paint_order = vec![];
+                            }
+                        });
+
+                        ui.menu_button("Sort", |ui| {
+                            for mode in settings::SortMode::ALL {
+                                ui.radio_value(&mut self.settings.sort, mode, mode.name());
+                            }
+                        });
+
+                        ui.menu_button("Theme", |ui| {
+                            for theme in palette::Theme::ALL {
+                                ui.radio_value(&mut self.settings.theme, theme, theme.name());
+                            }
+                        });
+
+                        ui.menu_button("Palette", |ui| {
+                            for kind in palette::PaletteKind::ALL {
+                                ui.add_enabled_ui(
+                                    kind != palette::PaletteKind::Custom
+                                        || self.custom_palette.is_some(),
+                                    |ui| {
+                                        ui.radio_value(
+                                            &mut self.settings.palette,
+                                            kind,
+                                            kind.name(),
+                                        );
+                                    },
+                                );
+                            }
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                ui.label("Custom palette file:");
+                                ui.text_edit_singleline(&mut self.settings.custom_palette);
+                            });
+                            if ui.button("Load").clicked() {
+                                self.load_custom_palette();
+                            }
+                            if let Some(status) = &self.palette_status {
+                                ui.label(status);
+                            }
+                        });
+
+                        ui.menu_button("Export", |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("File name:");
+                                ui.text_edit_singleline(&mut self.export_path);
+                            });
+                            if ui.button("Save as SVG").clicked() {
+                                self.export(window_size.width(), "svg");
+                            }
+                            if ui.button("Save as PNG").clicked() {
+                                self.export(window_size.width(), "png");
+                            }
+                            ui.separator();
+                            if ui.button("Save size report as CSV").clicked() {
+                                self.export(window_size.width(), "csv");
+                            }
+                            if ui.button("Save size report as JSON").clicked() {
+                                self.export(window_size.width(), "json");
+                            }
+                            if let Some(status) = &self.export_status {
+                                ui.label(status);
+                            }
                         });
+
+                        if let Some(query) = &mut self.search {
+                            let search = ui.add(
+                                egui::TextEdit::singleline(query)
+                                    .id(Id::new(SEARCH_ID))
+                                    .hint_text("Search names")
+                                    .desired_width(150.0),
+                            );
+                            if search.changed() {
+                                self.search_matches = self.data.find(query);
+                                self.search_hit = 0;
+                            }
+                            ui.label(format!("{} matches", self.search_matches.len()));
+                            if search.lost_focus() {
+                                if ui.input(|input| input.key_pressed(Key::Enter)) {
+                                    self.next_search_hit(ctx);
+                                } else if ui.input(|input| input.key_pressed(Key::Escape)) {
+                                    self.search = None;
+                                    self.search_matches = vec![];
+                                }
+                            }
+                        }
+
+                        if self.source.is_live()
+                            && ui
+                                .add_enabled(self.syncing.is_none(), egui::Button::new("Sync"))
+                                .clicked()
+                        {
+                            let ctx = ctx.clone();
+                            self.syncing = self.source.sync(move || ctx.request_repaint());
+                        }
+                        if let Some(status) = &self.sync_status {
+                            ui.label(status);
+                        }
+                        if let Some(status) = &self.action_status {
+                            ui.label(status);
+                        }
+                        if let Some(status) = &self.load_status {
+                            ui.label(status);
+                        }
                     });
                 },
             );
@@ -359,7 +1269,7 @@ impl eframe::App for MyApp {
                     order: egui::Order::Foreground,
                     id: Id::new(1),
                 })
-                .rect_filled(root_draw_anchor, 0.0, Color32::WHITE);
+                .rect_filled(root_draw_anchor, 0.0, self.chrome.root_bar);
 
             painter
                 .clone()
@@ -375,7 +1285,7 @@ impl eframe::App for MyApp {
                         size: 15.0,
                         family: FontFamily::Proportional,
                     },
-                    Color32::BLACK,
+                    self.chrome.text,
                 );
             ui.allocate_ui_at_rect(
                 Rect {
@@ -404,10 +1314,149 @@ impl eframe::App for MyApp {
             //Starts drawing the rest of the folders and files
             let potential_new_root = self.follow_paint_order(ui, root_draw_anchor);
             //assigning a new root if selected
-            match potential_new_root {
-                Some(_) => self.change_root(potential_new_root.unwrap()),
-                None => (),
+            if let Some(new_root) = potential_new_root {
+                self.change_root(new_root);
+                if std::mem::take(&mut self.focus_after_zoom) {
+                    self.refresh_paint_order();
+                    if let Some(first) = self.paint_order.first() {
+                        ctx.memory_mut(|memory| memory.request_focus(Id::new(first.id)));
+                    }
+                }
             }
         });
+        self.show_dialogs(ctx);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MyApp;
+    use crate::palette::{Chrome, Palette, Theme};
+    use crate::settings::Settings;
+    use eframe::egui;
+    use lb_rs::Uuid;
+
+    //Polls like the window does until the load started on `app` has finished
+    fn finish(app: &mut MyApp) {
+        for _ in 0..500 {
+            app.finish_loading();
+            if app.loading.is_none() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("loading never finished");
+    }
+
+    #[test]
+    fn startup_follows_the_platform_theme() {
+        let ctx = egui::Context::default();
+        let settings = Settings {
+            source: String::new(),
+            theme: Theme::System,
+            ..Default::default()
+        };
+        let light = MyApp::open(&ctx, settings.clone(), Some(eframe::Theme::Light));
+        assert!(!light.dark);
+        assert_eq!(light.palette, Palette::new(light.palette_kind, false, None));
+        assert_eq!(light.chrome, Chrome::new(false));
+        assert!(MyApp::open(&ctx, settings, Some(eframe::Theme::Dark)).dark);
+    }
+
+    #[test]
+    fn opening_a_source_replaces_state_tied_to_the_old_one() {
+        let ctx = egui::Context::default();
+        let mut app = MyApp::blank(Settings::default(), None);
+        app.start_loading(&ctx, "parth-doc-data.json".to_owned());
+        finish(&mut app);
+        assert!(!app.data.all_files.is_empty());
+
+        //Saving remembers the root without it sticking to the next source that's opened
+        let folder = app
+            .data
+            .get_top_level_order()
+            .into_iter()
+            .find(|id| app.data.all_files[id].file.is_folder())
+            .unwrap();
+        app.change_root(folder);
+        let saved = app.saved_settings();
+        assert_eq!(saved.root, Some(folder));
+        assert_eq!(app.settings.root, None);
+
+        //It's only zoomed back into when the tab is opened again on startup
+        let mut reopened = MyApp::open(&ctx, saved, None);
+        assert_eq!(reopened.saved_settings().root, Some(folder));
+        finish(&mut reopened);
+        assert_eq!(reopened.data.current_root, folder);
+
+        let stale = Uuid::from_u128(1);
+        app.selected.insert(stale);
+        app.flashes.insert(stale, 0.0);
+        app.families.insert(stale, 3);
+        app.details = Some(stale);
+        app.action_status = Some("Moved".to_owned());
+        app.search = Some("zzz-no-such-name".to_owned());
+        app.search_matches = vec![stale];
+        app.refresh_paint_order();
+        assert!(!app.paint_order.is_empty());
+
+        //A failed load leaves everything as it was and says why
+        app.start_loading(&ctx, "no-such-snapshot.json".to_owned());
+        finish(&mut app);
+        assert!(app.load_status.is_some());
+        assert!(app.selected.contains(&stale));
+        assert!(!app.paint_order.is_empty());
+
+        app.start_loading(&ctx, "parth-doc-data.json".to_owned());
+        finish(&mut app);
+        assert_eq!(app.load_status, None);
+        assert_eq!(app.action_status, None);
+        assert!(app.selected.is_empty());
+        assert!(app.flashes.is_empty());
+        assert!(app.families.is_empty());
+        assert!(app.colors.is_empty());
+        assert_eq!(app.details, None);
+        assert!(app.search_matches.is_empty());
+        assert!(app.paint_order.is_empty());
+        assert_eq!(app.settings.source, "parth-doc-data.json");
+        assert_eq!(app.data.current_root, app.data.overall_root);
+    }
+
+    #[test]
+    fn back_and_forward_retrace_root_changes() {
+        let ctx = egui::Context::default();
+        let mut app = MyApp::blank(Settings::default(), None);
+        app.start_loading(&ctx, "parth-doc-data.json".to_owned());
+        finish(&mut app);
+        let overall = app.data.overall_root;
+        let folders: Vec<Uuid> = app
+            .data
+            .get_top_level_order()
+            .into_iter()
+            .filter(|id| app.data.all_files[id].file.is_folder())
+            .take(2)
+            .collect();
+
+        app.go_back();
+        assert_eq!(app.data.current_root, overall);
+        app.change_root(folders[0]);
+        app.change_root(folders[1]);
+        //Choosing the root that's already showing isn't a step
+        app.change_root(folders[1]);
+        app.go_back();
+        assert_eq!(app.data.current_root, folders[0]);
+        app.go_back();
+        assert_eq!(app.data.current_root, overall);
+        app.go_forward();
+        assert_eq!(app.data.current_root, folders[0]);
+
+        //Going somewhere new drops what was ahead
+        app.reset_root();
+        assert!(app.forward.is_empty());
+        app.go_back();
+        assert_eq!(app.data.current_root, folders[0]);
+        app.go_back();
+        app.go_back();
+        assert_eq!(app.data.current_root, overall);
     }
 }
@@ -0,0 +1,426 @@
+use colors_transform::{self, Color};
+use eframe::egui::{Color32, Visuals};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+//WCAG AA for normal sized text, which is what segment labels are drawn at
+pub const MIN_LABEL_CONTRAST: f32 = 4.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    Light,
+    Dark,
+    System,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Light, Theme::Dark, Theme::System];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Light => "Light",
+            Theme::Dark => "Dark",
+            Theme::System => "System",
+        }
+    }
+
+    //Falls back to dark like egui does when the platform won't say what it prefers
+    pub fn is_dark(&self, system: Option<eframe::Theme>) -> bool {
+        match self {
+            Theme::Light => false,
+            Theme::Dark => true,
+            Theme::System => system != Some(eframe::Theme::Light),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteKind {
+    Classic,
+    Viridis,
+    OkabeIto,
+    Custom,
+}
+
+impl PaletteKind {
+    pub const ALL: [PaletteKind; 4] = [
+        PaletteKind::Classic,
+        PaletteKind::Viridis,
+        PaletteKind::OkabeIto,
+        PaletteKind::Custom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PaletteKind::Classic => "Classic",
+            PaletteKind::Viridis => "Viridis",
+            PaletteKind::OkabeIto => "Okabe-Ito",
+            PaletteKind::Custom => "Custom",
+        }
+    }
+}
+
+fn relative_luminance(color: Color32) -> f32 {
+    let channel = |value: u8| {
+        let value = value as f32 / 255.0;
+        if value <= 0.03928 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(color.r()) + 0.7152 * channel(color.g()) + 0.0722 * channel(color.b())
+}
+
+pub fn contrast_ratio(first: Color32, second: Color32) -> f32 {
+    let (first, second) = (relative_luminance(first), relative_luminance(second));
+    (first.max(second) + 0.05) / (first.min(second) + 0.05)
+}
+
+//Picks a readable text color for a given background by flipping its lightness,
+//falling back to black or white when the tinted version is too faint to read
+pub fn label_color(background: Color32) -> Color32 {
+    let tinted = tinted_label(background);
+    if contrast_ratio(background, tinted) >= MIN_LABEL_CONTRAST {
+        tinted
+    } else if contrast_ratio(background, Color32::BLACK)
+        > contrast_ratio(background, Color32::WHITE)
+    {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    }
+}
+
+//The background's own hue with its lightness flipped, the label color a shade is meant to have
+fn tinted_label(background: Color32) -> Color32 {
+    let hsl_color = colors_transform::Rgb::from(
+        background.r().into(),
+        background.g().into(),
+        background.b().into(),
+    )
+    .to_hsl();
+    let luminance = if hsl_color.get_lightness() > 50.0 {
+        (hsl_color.get_lightness() - 50.0) / 100.0
+    } else {
+        (hsl_color.get_lightness() + 50.0) / 100.0
+    };
+    Color32::from_hex(
+        &(color_art::color!(
+            HSL,
+            hsl_color.get_hue(),
+            hsl_color.get_saturation() / 100.0,
+            luminance
+        ))
+        .hex(),
+    )
+    .unwrap_or(Color32::DEBUG_COLOR)
+}
+
+//What a segment of this color looks like on the chart, translucent ones let the background through
+fn over(color: Color32, background: Color32) -> Color32 {
+    let behind = |channel: u8| (channel as u32 * (255 - color.a() as u32) / 255) as u8;
+    Color32::from_rgb(
+        color.r().saturating_add(behind(background.r())),
+        color.g().saturating_add(behind(background.g())),
+        color.b().saturating_add(behind(background.b())),
+    )
+}
+
+//Every color has to keep the label `label_color` draws on it readable against what actually shows
+//behind that label, the same rule for custom and built-in palettes
+fn validate(colors: &[Color32], background: Color32) -> Result<(), String> {
+    let unreadable: Vec<String> = colors
+        .iter()
+        .filter(|color| {
+            contrast_ratio(over(**color, background), label_color(**color)) < MIN_LABEL_CONTRAST
+        })
+        .map(|color| color.to_hex())
+        .collect();
+    if unreadable.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Labels would be hard to read on {} (contrast below {}:1)",
+            unreadable.join(", "),
+            MIN_LABEL_CONTRAST
+        ))
+    }
+}
+
+//Colors for everything around the slices: background, root strip, outlines and text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chrome {
+    pub background: Color32,
+    pub root_bar: Color32,
+    pub border: Color32,
+    pub text: Color32,
+}
+
+impl Chrome {
+    pub fn new(dark: bool) -> Self {
+        if dark {
+            Self {
+                background: Visuals::dark().panel_fill,
+                root_bar: Visuals::dark().extreme_bg_color,
+                border: Color32::from_gray(15),
+                text: Color32::from_gray(230),
+            }
+        } else {
+            Self {
+                background: Color32::WHITE,
+                root_bar: Color32::WHITE,
+                border: Color32::BLACK,
+                text: Color32::BLACK,
+            }
+        }
+    }
+}
+
+//Each family is one hue, going from the first layer outwards
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub families: Vec<[Color32; 6]>,
+}
+
+//A palette as written in a config file, every color is a `#rrggbb` or `#rrggbbaa` string.
+//Families may list fewer than six shades, the rest are derived from the first one
+#[derive(Debug, Clone, Deserialize)]
+struct PaletteConfig {
+    families: Vec<Vec<String>>,
+    dark_families: Option<Vec<Vec<String>>>,
+}
+
+//A user palette that has been parsed and checked, ready for either theme
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomPalette {
+    light: Palette,
+    dark: Palette,
+}
+
+impl CustomPalette {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+        let config: PaletteConfig = serde_json::from_str(&contents)
+            .map_err(|err| format!("{} isn't a valid palette: {}", path, err))?;
+
+        let light = Palette::from_config(&config.families, false)?;
+        let dark = match &config.dark_families {
+            Some(families) => Palette::from_config(families, true)?,
+            None => Palette::from_config(&config.families, true)?,
+        };
+        Ok(Self { light, dark })
+    }
+}
+
+impl Palette {
+    pub fn new(kind: PaletteKind, dark: bool, custom: Option<&CustomPalette>) -> Self {
+        match (kind, custom) {
+            (PaletteKind::Viridis, _) => Self::shaded(
+                &[
+                    Color32::from_rgb(68, 1, 84),
+                    Color32::from_rgb(59, 82, 139),
+                    Color32::from_rgb(33, 145, 140),
+                    Color32::from_rgb(94, 201, 98),
+                    Color32::from_rgb(253, 231, 37),
+                ],
+                dark,
+            ),
+            (PaletteKind::OkabeIto, _) => Self::shaded(
+                &[
+                    Color32::from_rgb(230, 159, 0),
+                    Color32::from_rgb(86, 180, 233),
+                    Color32::from_rgb(0, 158, 115),
+                    Color32::from_rgb(240, 228, 66),
+                    Color32::from_rgb(0, 114, 178),
+                    Color32::from_rgb(213, 94, 0),
+                    Color32::from_rgb(204, 121, 167),
+                ],
+                dark,
+            ),
+            (PaletteKind::Custom, Some(custom)) if dark => custom.dark.clone(),
+            (PaletteKind::Custom, Some(custom)) => custom.light.clone(),
+            _ if dark => Self::classic_dark(),
+            _ => Self::classic_light(),
+        }
+    }
+
+    //Wraps around both ways so any family and depth has a color
+    pub fn color(&self, family: usize, depth: usize) -> Color32 {
+        self.families[family % self.families.len()][depth % 6]
+    }
+
+    //Light themes fade each hue towards white as layers go out, dark themes towards black
+    fn shade(base: Color32, dark: bool) -> [Color32; 6] {
+        let target = if dark { Color32::BLACK } else { Color32::WHITE };
+        let mix = |from: u8, to: u8, amount: f32| {
+            (from as f32 + (to as f32 - from as f32) * amount).round() as u8
+        };
+        std::array::from_fn(|shade| {
+            let amount = shade as f32 * if dark { 0.1 } else { 0.12 };
+            Color32::from_rgb(
+                mix(base.r(), target.r(), amount),
+                mix(base.g(), target.g(), amount),
+                mix(base.b(), target.b(), amount),
+            )
+        })
+    }
+
+    fn shaded(bases: &[Color32], dark: bool) -> Self {
+        Self {
+            families: bases.iter().map(|base| Self::shade(*base, dark)).collect(),
+        }
+    }
+
+    fn from_config(families: &[Vec<String>], dark: bool) -> Result<Self, String> {
+        if families.is_empty() {
+            return Err("A palette needs at least one family".to_owned());
+        }
+        let mut palette = Self { families: vec![] };
+        for family in families {
+            if family.is_empty() || family.len() > 6 {
+                return Err("Each family needs between one and six colors".to_owned());
+            }
+            let colors = family
+                .iter()
+                .map(|hex| {
+                    Color32::from_hex(hex)
+                        .map_err(|_| format!("{} isn't a #rrggbb or #rrggbbaa color", hex))
+                })
+                .collect::<Result<Vec<Color32>, String>>()?;
+            validate(&colors, Chrome::new(dark).background)?;
+            let mut shades = Self::shade(colors[0], dark);
+            shades[..colors.len()].copy_from_slice(&colors);
+            palette.families.push(shades);
+        }
+        Ok(palette)
+    }
+
+    fn classic_light() -> Self {
+        Self {
+            families: vec![
+                //red
+                [
+                    Color32::from_rgb(128, 15, 47),
+                    Color32::from_rgb(164, 19, 60),
+                    Color32::from_rgb(201, 24, 74),
+                    Color32::from_rgb(255, 77, 109),
+                    Color32::from_rgb(255, 117, 143),
+                    Color32::from_rgb(255, 143, 163),
+                ],
+                //green
+                [
+                    Color32::from_rgb(27, 67, 50),
+                    Color32::from_rgb(45, 106, 79),
+                    Color32::from_rgb(64, 145, 108),
+                    Color32::from_rgb(82, 183, 136),
+                    Color32::from_rgb(116, 198, 157),
+                    Color32::from_rgb(149, 213, 178),
+                ],
+                //blue
+                [
+                    Color32::from_rgb(2, 62, 138),
+                    Color32::from_rgb(0, 119, 182),
+                    Color32::from_rgb(0, 150, 199),
+                    Color32::from_rgb(0, 180, 216),
+                    Color32::from_rgb(72, 202, 228),
+                    Color32::from_rgb(144, 224, 239),
+                ],
+            ],
+        }
+    }
+
+    //Brightest at the root so the first layers stand out against a dark panel
+    fn classic_dark() -> Self {
+        Self {
+            families: vec![
+                //red
+                [
+                    Color32::from_rgb(255, 117, 143),
+                    Color32::from_rgb(239, 87, 115),
+                    Color32::from_rgb(214, 51, 90),
+                    Color32::from_rgb(183, 33, 70),
+                    Color32::from_rgb(150, 24, 56),
+                    Color32::from_rgb(118, 18, 44),
+                ],
+                //green
+                [
+                    Color32::from_rgb(116, 198, 157),
+                    Color32::from_rgb(88, 178, 134),
+                    Color32::from_rgb(64, 150, 111),
+                    Color32::from_rgb(49, 122, 89),
+                    Color32::from_rgb(38, 96, 70),
+                    Color32::from_rgb(29, 74, 54),
+                ],
+                //blue
+                [
+                    Color32::from_rgb(114, 212, 236),
+                    Color32::from_rgb(64, 190, 224),
+                    Color32::from_rgb(16, 162, 207),
+                    Color32::from_rgb(0, 133, 188),
+                    Color32::from_rgb(4, 104, 164),
+                    Color32::from_rgb(6, 76, 140),
+                ],
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{contrast_ratio, label_color, validate, Chrome, Palette, PaletteKind, Theme};
+    use eframe::egui::Color32;
+
+    #[test]
+    fn system_theme_follows_the_platform() {
+        assert!(!Theme::System.is_dark(Some(eframe::Theme::Light)));
+        assert!(Theme::System.is_dark(Some(eframe::Theme::Dark)));
+        assert!(Theme::System.is_dark(None));
+        assert!(!Theme::Light.is_dark(Some(eframe::Theme::Dark)));
+        assert!(Theme::Dark.is_dark(Some(eframe::Theme::Light)));
+    }
+
+    #[test]
+    fn contrast_extremes() {
+        assert!((contrast_ratio(Color32::BLACK, Color32::WHITE) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(Color32::WHITE, Color32::WHITE) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn built_in_palettes_pass_validation() {
+        for kind in PaletteKind::ALL {
+            for dark in [false, true] {
+                let palette = Palette::new(kind, dark, None);
+                let shades: Vec<Color32> = palette.families.into_iter().flatten().collect();
+                assert_eq!(validate(&shades, Chrome::new(dark).background), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn config_shades_are_filled_in() {
+        let families = vec![vec!["#00507a".to_owned()]];
+        let palette = Palette::from_config(&families, false).unwrap();
+        assert_eq!(palette.families.len(), 1);
+        assert_eq!(palette.families[0][0], Color32::from_rgb(0, 80, 122));
+        assert_ne!(palette.families[0][1], palette.families[0][0]);
+        assert!(Palette::from_config(&[vec!["blue".to_owned()]], false).is_err());
+        assert!(Palette::from_config(&[], false).is_err());
+    }
+
+    #[test]
+    fn colors_with_faint_labels_are_rejected() {
+        //Okabe-Ito orange is readable with the black label it falls back to, as a custom color too
+        let orange = Color32::from_rgb(230, 159, 0);
+        assert_eq!(label_color(orange), Color32::BLACK);
+        assert!(Palette::from_config(&[vec!["#e69f00".to_owned()]], false).is_ok());
+
+        //A faint black lets the light background through, while its label is picked for a dark shade
+        let families = vec![vec!["#00507a".to_owned()], vec!["#00000020".to_owned()]];
+        let err = Palette::from_config(&families, false).unwrap_err();
+        assert!(err.contains("#00000020"));
+        assert!(!err.contains("#00507a"));
+        assert!(Palette::from_config(&families, true).is_ok());
+    }
+}
@@ -0,0 +1,92 @@
+pub use crate::data::SortMode;
+use crate::palette::{PaletteKind, Theme};
+use crate::DEFAULT_INPUT;
+use lb_rs::Uuid;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GroupUnit {
+    Bytes,
+    Percent,
+    Pixels,
+}
+
+impl GroupUnit {
+    pub const ALL: [GroupUnit; 3] = [GroupUnit::Bytes, GroupUnit::Percent, GroupUnit::Pixels];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupUnit::Bytes => "bytes",
+            GroupUnit::Percent => "%",
+            GroupUnit::Pixels => "px",
+        }
+    }
+}
+
+//Everything that should survive closing the window, saved through eframe's storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub layer_height: f32,
+    pub source: String,
+    pub root: Option<Uuid>,
+    pub theme: Theme,
+    pub palette: PaletteKind,
+    pub custom_palette: String,
+    pub group_unit: GroupUnit,
+    pub group_amount: f32,
+    pub fit_depth_to_window: bool,
+    pub sort: SortMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            layer_height: 50.0,
+            source: DEFAULT_INPUT.to_owned(),
+            root: None,
+            theme: Theme::System,
+            palette: PaletteKind::Classic,
+            custom_palette: String::new(),
+            group_unit: GroupUnit::Pixels,
+            group_amount: 2.0,
+            fit_depth_to_window: true,
+            sort: SortMode::Size,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default()
+    }
+}
+
+//Where the open tabs are kept, apart from the single tab's settings older versions saved
+const SESSION_KEY: &str = "tabs";
+
+//The settings of every open tab and which one was showing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub tabs: Vec<Settings>,
+    pub active: usize,
+}
+
+impl Session {
+    //Falls back to one tab with the settings saved before there were tabs
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        storage
+            .and_then(|storage| eframe::get_value::<Session>(storage, SESSION_KEY))
+            .filter(|session| !session.tabs.is_empty())
+            .unwrap_or_else(|| Session {
+                tabs: vec![Settings::load(storage)],
+                active: 0,
+            })
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SESSION_KEY, self);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::data::FileRow;
+use crate::loading::Progress;
+use lb_rs::model::file::{File, Share, ShareMode};
+use lb_rs::model::file_metadata::FileType;
+use lb_rs::Uuid;
+use std::collections::HashMap;
+use std::fs::File as FsFile;
+use std::io::{self, BufWriter, Read, Write};
+
+//Binary snapshots start with these bytes followed by a version, which is how they're told apart from JSON
+pub const MAGIC: &[u8; 4] = b"SISN";
+//Bumped whenever the layout below changes; older versions stay readable
+pub const VERSION: u8 = 1;
+
+//Layout of version 1, with every number after the header a LEB128 varint:
+//  magic, version byte
+//  string count, then each string as its length and UTF-8 bytes
+//  row count, then each row as
+//    id (16 bytes), parent (16 bytes), name, file type byte (0 document, 1 folder, 2 link followed
+//    by the 16 byte target), last modified, last modified by, size, share count, then each share as
+//    a mode byte (0 write, 1 read), shared by and shared with
+//Names and usernames are written as indexes into the string table, so repeated ones are stored once
+pub fn write(rows: &[FileRow], out: &mut impl Write) -> io::Result<()> {
+    //Gathered up front so the table can be written before any row
+    let mut strings: Vec<&str> = vec![];
+    let mut indexes: HashMap<&str, u64> = HashMap::new();
+    for row in rows {
+        let file = &row.file;
+        let shared = file
+            .shares
+            .iter()
+            .flat_map(|share| [&share.shared_by, &share.shared_with]);
+        for string in [&file.name, &file.last_modified_by]
+            .into_iter()
+            .chain(shared)
+        {
+            indexes.entry(string).or_insert_with(|| {
+                strings.push(string);
+                strings.len() as u64 - 1
+            });
+        }
+    }
+
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+    write_varint(out, strings.len() as u64)?;
+    for string in strings {
+        write_varint(out, string.len() as u64)?;
+        out.write_all(string.as_bytes())?;
+    }
+    write_varint(out, rows.len() as u64)?;
+    for row in rows {
+        let file = &row.file;
+        out.write_all(file.id.as_bytes())?;
+        out.write_all(file.parent.as_bytes())?;
+        write_varint(out, indexes[file.name.as_str()])?;
+        match file.file_type {
+            FileType::Document => out.write_all(&[0])?,
+            FileType::Folder => out.write_all(&[1])?,
+            FileType::Link { target } => {
+                out.write_all(&[2])?;
+                out.write_all(target.as_bytes())?;
+            }
+        }
+        write_varint(out, file.last_modified)?;
+        write_varint(out, indexes[file.last_modified_by.as_str()])?;
+        write_varint(out, row.size)?;
+        write_varint(out, file.shares.len() as u64)?;
+        for share in &file.shares {
+            out.write_all(&[match share.mode {
+                ShareMode::Write => 0,
+                ShareMode::Read => 1,
+            }])?;
+            write_varint(out, indexes[share.shared_by.as_str()])?;
+            write_varint(out, indexes[share.shared_with.as_str()])?;
+        }
+    }
+    Ok(())
+}
+
+pub fn save(rows: &[FileRow], path: &str) -> io::Result<()> {
+    let mut out = BufWriter::new(FsFile::create(path)?);
+    write(rows, &mut out)?;
+    out.flush()
+}
+
+//Hands each row to `each` as soon as it's decoded, counting them and stopping if loading is cancelled.
+//The magic bytes are expected to have been checked and skipped already
+pub fn read_rows(
+    mut reader: impl Read,
+    progress: &Progress,
+    mut each: impl FnMut(FileRow),
+) -> Result<(), String> {
+    let reader = &mut reader;
+    let version = read_byte(reader)?;
+    if version == 0 || version > VERSION {
+        return Err(format!(
+            "Snapshot version {} isn't supported, this build reads up to version {}",
+            version, VERSION
+        ));
+    }
+    let string_count = read_varint(reader)?;
+    let mut strings = Vec::with_capacity(string_count.min(1 << 16) as usize);
+    for _ in 0..string_count {
+        let len = read_varint(reader)?;
+        let mut bytes = vec![];
+        reader
+            .take(len)
+            .read_to_end(&mut bytes)
+            .map_err(truncated)?;
+        if bytes.len() as u64 != len {
+            return Err(truncated(io::ErrorKind::UnexpectedEof.into()));
+        }
+        strings.push(String::from_utf8(bytes).map_err(|_| "Snapshot has a name that isn't UTF-8")?);
+    }
+
+    let row_count = read_varint(reader)?;
+    for _ in 0..row_count {
+        if progress.is_cancelled() {
+            return Err("Loading was cancelled".to_owned());
+        }
+        let id = read_uuid(reader)?;
+        let parent = read_uuid(reader)?;
+        let name = read_string(reader, &strings)?;
+        let file_type = match read_byte(reader)? {
+            0 => FileType::Document,
+            1 => FileType::Folder,
+            2 => FileType::Link {
+                target: read_uuid(reader)?,
+            },
+            kind => return Err(format!("Snapshot has an unknown file type {}", kind)),
+        };
+        let last_modified = read_varint(reader)?;
+        let last_modified_by = read_string(reader, &strings)?;
+        let size = read_varint(reader)?;
+        let share_count = read_varint(reader)?;
+        let mut shares = vec![];
+        for _ in 0..share_count {
+            let mode = match read_byte(reader)? {
+                0 => ShareMode::Write,
+                1 => ShareMode::Read,
+                mode => return Err(format!("Snapshot has an unknown share mode {}", mode)),
+            };
+            shares.push(Share {
+                mode,
+                shared_by: read_string(reader, &strings)?,
+                shared_with: read_string(reader, &strings)?,
+            });
+        }
+        progress.parsed();
+        each(FileRow {
+            file: File {
+                id,
+                parent,
+                name,
+                file_type,
+                last_modified,
+                last_modified_by,
+                shares,
+            },
+            size,
+        });
+    }
+    Ok(())
+}
+
+fn truncated(err: io::Error) -> String {
+    format!("Snapshot is cut short: {}", err)
+}
+
+fn read_string(reader: &mut impl Read, strings: &[String]) -> Result<String, String> {
+    let index = read_varint(reader)?;
+    strings
+        .get(index as usize)
+        .cloned()
+        .ok_or(format!("Snapshot refers to missing string {}", index))
+}
+
+fn read_byte(reader: &mut impl Read) -> Result<u8, String> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte).map_err(truncated)?;
+    Ok(byte[0])
+}
+
+fn read_uuid(reader: &mut impl Read) -> Result<Uuid, String> {
+    let mut bytes = [0; 16];
+    reader.read_exact(&mut bytes).map_err(truncated)?;
+    Ok(Uuid::from_bytes(bytes))
+}
+
+fn write_varint(out: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64, String> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = read_byte(reader)?;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("Snapshot has a number that's too long".to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_rows, write, MAGIC};
+    use crate::data::FileRow;
+    use crate::loading::Progress;
+
+    #[test]
+    fn round_trips_the_json_snapshot() {
+        let json = std::fs::read_to_string("parth-doc-data.json").unwrap();
+        let rows: Vec<FileRow> = serde_json::from_str(&json).unwrap();
+        let mut bytes = vec![];
+        write(&rows, &mut bytes).unwrap();
+        assert!(bytes.starts_with(MAGIC));
+        assert!(bytes.len() < json.len() / 4);
+
+        let mut read = vec![];
+        read_rows(&bytes[MAGIC.len()..], &Progress::default(), |row| {
+            read.push(row)
+        })
+        .unwrap();
+        assert_eq!(read, rows);
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(read_rows(&bytes[MAGIC.len()..], &Progress::default(), |_| ()).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::data::{Data, FileRow};
+use crate::loading::Progress;
+use lb_rs::blocking::Lb;
+use lb_rs::model::core_config::Config;
+use lb_rs::Uuid;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryIter};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+//How often a Lockbook account is checked for changes while the window is open
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+//Where the inspected files come from; only a Lockbook account can have changes written back
+pub enum Source {
+    //A snapshot of an account exported as JSON
+    Json(String),
+    //The Lockbook data directory of an account signed in on this machine
+    Lockbook(Lb),
+}
+
+//Listings sent by a polling thread. Dropping it stops the thread before its next check
+pub struct Watcher {
+    receiver: Receiver<Result<Vec<FileRow>, String>>,
+    stopped: Arc<AtomicBool>,
+    //Skips checks while set, for tabs that aren't showing
+    paused: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    //Calls `load` every `interval`, sending what it returns whenever that differs from last time
+    fn start(
+        interval: Duration,
+        load: impl Fn() -> Result<Vec<FileRow>, String> + Send + 'static,
+        wake: impl Fn() + Send + 'static,
+    ) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let paused = Arc::new(AtomicBool::new(false));
+        let thread_paused = paused.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut last: Option<Vec<FileRow>> = None;
+            loop {
+                thread::sleep(interval);
+                if thread_stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                if thread_paused.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let mut rows = load();
+                if let Ok(rows) = &mut rows {
+                    rows.sort_by_key(|row| row.file.id);
+                    if last.as_ref() == Some(rows) {
+                        continue;
+                    }
+                    last = Some(rows.clone());
+                }
+                if sender.send(rows).is_err() {
+                    break;
+                }
+                wake();
+            }
+        });
+        Self {
+            receiver,
+            stopped,
+            paused,
+        }
+    }
+
+    //Changes made while paused are picked up by the first check after resuming
+    pub fn pause(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn listings(&self) -> TryIter<'_, Result<Vec<FileRow>, String>> {
+        self.receiver.try_iter()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Source {
+    //Directories are treated as Lockbook data directories, anything else as a JSON snapshot
+    pub fn open(path: &str) -> Result<Self, String> {
+        if !Path::new(path).is_dir() {
+            return Ok(Source::Json(path.to_owned()));
+        }
+        Lb::init(Config {
+            writeable_path: path.to_owned(),
+            background_work: false,
+            logs: false,
+            stdout_logs: false,
+            colored_logs: false,
+        })
+        .map(Source::Lockbook)
+        .map_err(|err| format!("couldn't open Lockbook data at {}: {}", path, err))
+    }
+
+    pub fn is_live(&self) -> bool {
+        matches!(self, Source::Lockbook(_))
+    }
+
+    //Lists a Lockbook account's files; JSON snapshots are only read whole through load_data
+    pub fn load(&self) -> Result<Vec<FileRow>, String> {
+        self.list(&Progress::default())
+    }
+
+    //Builds the chart data, streaming a JSON snapshot row by row instead of listing it first
+    pub fn load_data(&self, progress: &Progress) -> Result<Data, String> {
+        match self {
+            Source::Json(path) => Data::from_file(path, progress),
+            Source::Lockbook(_) => Data::init_with_progress(self.list(progress)?, progress)
+                .ok_or("Loading was cancelled".to_owned()),
+        }
+    }
+
+    fn list(&self, progress: &Progress) -> Result<Vec<FileRow>, String> {
+        match self {
+            Source::Json(_) => Err("a JSON snapshot is read with load_data".to_owned()),
+            Source::Lockbook(lb) => {
+                //Neither call can be interrupted, so cancelling takes effect between them
+                let sizes = lb
+                    .get_uncompressed_usage_breakdown()
+                    .map_err(|err| err.to_string())?;
+                if progress.is_cancelled() {
+                    return Err("Loading was cancelled".to_owned());
+                }
+                let files = lb.list_metadatas().map_err(|err| err.to_string())?;
+                if progress.is_cancelled() {
+                    return Err("Loading was cancelled".to_owned());
+                }
+                progress.rows_parsed.store(files.len(), Ordering::Relaxed);
+                Ok(files
+                    .into_iter()
+                    .map(|file| FileRow {
+                        size: sizes.get(&file.id).copied().unwrap_or_default() as u64,
+                        file,
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    fn lockbook(&self) -> Result<&Lb, String> {
+        match self {
+            Source::Lockbook(lb) => Ok(lb),
+            Source::Json(_) => Err("a JSON snapshot can't be changed".to_owned()),
+        }
+    }
+
+    pub fn rename(&self, id: Uuid, name: &str) -> Result<(), String> {
+        self.lockbook()?
+            .rename_file(&id, name)
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn move_file(&self, id: Uuid, new_parent: Uuid) -> Result<(), String> {
+        self.lockbook()?
+            .move_file(&id, &new_parent)
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn delete(&self, id: Uuid) -> Result<(), String> {
+        self.lockbook()?
+            .delete_file(&id)
+            .map_err(|err| err.to_string())
+    }
+
+    //Exports the file to the temp directory and hands it to the system's default app
+    pub fn open_file(&self, id: Uuid, name: &str) -> Result<(), String> {
+        let destination = std::env::temp_dir().join("space_inspector");
+        std::fs::create_dir_all(&destination).map_err(|err| err.to_string())?;
+        self.lockbook()?
+            .export_files(id, destination.clone(), false, &None)
+            .map_err(|err| err.to_string())?;
+        let opener = if cfg!(target_os = "macos") {
+            "open"
+        } else if cfg!(target_os = "windows") {
+            "explorer"
+        } else {
+            "xdg-open"
+        };
+        Command::new(opener)
+            .arg(destination.join(name))
+            .spawn()
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    //Checks a Lockbook account for changes on a worker thread, sending a listing each time it differs.
+    //`wake` is called after every send so the window can pick it up
+    pub fn watch(&self, wake: impl Fn() + Send + 'static) -> Option<Watcher> {
+        let source = Source::Lockbook(self.lockbook().ok()?.clone());
+        Some(Watcher::start(POLL_INTERVAL, move || source.load(), wake))
+    }
+
+    //Syncs a Lockbook account on a worker thread, sending progress messages and then how it went
+    pub fn sync(&self, wake: impl Fn() + Clone + Send + 'static) -> Option<Receiver<String>> {
+        let lb = self.lockbook().ok()?.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let progress_wake = wake.clone();
+            let result = lb.sync(Some(Box::new(move |progress| {
+                let _ = progress_sender.send(progress.to_string());
+                progress_wake();
+            })));
+            let _ = sender.send(match result {
+                Ok(_) => "Sync finished".to_owned(),
+                Err(err) => format!("Sync failed: {}", err),
+            });
+            wake();
+        });
+        Some(receiver)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Watcher;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn dropping_a_watcher_stops_its_thread() {
+        //The thread owns the load closure, so the marker is only released once the thread is gone
+        let marker = Arc::new(());
+        let thread_marker = marker.clone();
+        let watcher = Watcher::start(
+            Duration::from_millis(5),
+            move || {
+                let _ = &thread_marker;
+                Ok(vec![])
+            },
+            || (),
+        );
+        thread::sleep(Duration::from_millis(50));
+        //An unchanged listing is only sent the first time
+        assert_eq!(watcher.listings().count(), 1);
+        assert_eq!(Arc::strong_count(&marker), 2);
+
+        drop(watcher);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(Arc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn a_paused_watcher_stops_checking() {
+        let checks = Arc::new(AtomicUsize::new(0));
+        let thread_checks = checks.clone();
+        let watcher = Watcher::start(
+            Duration::from_millis(5),
+            move || {
+                thread_checks.fetch_add(1, Ordering::Relaxed);
+                Ok(vec![])
+            },
+            || (),
+        );
+        watcher.pause(true);
+        thread::sleep(Duration::from_millis(20));
+        let before = checks.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(checks.load(Ordering::Relaxed), before);
+
+        watcher.pause(false);
+        thread::sleep(Duration::from_millis(50));
+        assert!(checks.load(Ordering::Relaxed) > before);
+    }
+}
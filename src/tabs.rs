@@ -0,0 +1,139 @@
+use crate::settings::{Session, Settings};
+use crate::MyApp;
+use eframe::egui;
+
+//What was clicked in the tab strip, applied once the showing tab is done drawing
+pub enum TabAction {
+    Switch(usize),
+    New,
+    Close(usize),
+}
+
+//The strip of tabs as the showing tab draws it in its menu bar
+pub struct TabBar {
+    names: Vec<String>,
+    active: usize,
+    action: Option<TabAction>,
+}
+
+impl TabBar {
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        for (index, name) in self.names.iter().enumerate() {
+            if ui.selectable_label(index == self.active, name).clicked() {
+                self.action = Some(TabAction::Switch(index));
+            }
+        }
+        if self.names.len() > 1 && ui.button("×").on_hover_text("Close this tab").clicked() {
+            self.action = Some(TabAction::Close(self.active));
+        }
+        if ui.button("+").on_hover_text("New tab").clicked() {
+            self.action = Some(TabAction::New);
+        }
+        ui.separator();
+    }
+}
+
+//Several accounts or snapshots open side by side. Each tab is a whole chart with its own source,
+//root, history, selection and settings, and only the showing one is drawn
+pub struct Tabs {
+    tabs: Vec<MyApp>,
+    active: usize,
+}
+
+impl Tabs {
+    pub fn init(cc: &eframe::CreationContext) -> Self {
+        let session = Session::load(cc.storage);
+        let tabs: Vec<MyApp> = session
+            .tabs
+            .into_iter()
+            .map(|settings| MyApp::open(&cc.egui_ctx, settings, cc.integration_info.system_theme))
+            .collect();
+        let mut tabs = Self {
+            active: session.active.min(tabs.len() - 1),
+            tabs,
+        };
+        tabs.share_theme();
+        tabs
+    }
+
+    //The theme is the window's, since egui's visuals are, so the showing tab's choice goes to all
+    fn share_theme(&mut self) {
+        let theme = self.tabs[self.active].settings.theme;
+        for tab in &mut self.tabs {
+            tab.settings.theme = theme;
+        }
+    }
+}
+
+impl eframe::App for Tabs {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        Session {
+            tabs: self.tabs.iter().map(MyApp::saved_settings).collect(),
+            active: self.active,
+        }
+        .save(storage);
+    }
+
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut bar = TabBar {
+            names: self.tabs.iter().map(MyApp::tab_name).collect(),
+            active: self.active,
+            action: None,
+        };
+        //Only the showing tab keeps checking its Lockbook account, the rest catch up when switched to
+        for (index, tab) in self.tabs.iter().enumerate() {
+            tab.pause_watching(index != self.active);
+        }
+        self.tabs[self.active].show(ctx, frame, &mut bar);
+        self.share_theme();
+        match bar.action {
+            Some(TabAction::Switch(index)) => self.active = index,
+            //A new tab starts empty, with the settings of the one it was opened from but not its source
+            Some(TabAction::New) => {
+                let settings = Settings {
+                    source: String::new(),
+                    root: None,
+                    ..self.tabs[self.active].settings.clone()
+                };
+                self.tabs
+                    .push(MyApp::blank(settings, frame.info().system_theme));
+                self.active = self.tabs.len() - 1;
+            }
+            Some(TabAction::Close(index)) => {
+                self.tabs.remove(index);
+                self.active = self.active.min(self.tabs.len() - 1);
+            }
+            None => return,
+        }
+        ctx.request_repaint();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Tabs;
+    use crate::palette::Theme;
+    use crate::settings::Settings;
+    use crate::MyApp;
+
+    #[test]
+    fn the_showing_tab_sets_the_theme_for_every_tab() {
+        let tab = |theme| {
+            MyApp::blank(
+                Settings {
+                    theme,
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+        let mut tabs = Tabs {
+            tabs: vec![tab(Theme::Light), tab(Theme::Dark), tab(Theme::System)],
+            active: 1,
+        };
+        tabs.share_theme();
+        for tab in &tabs.tabs {
+            assert_eq!(tab.settings.theme, Theme::Dark);
+        }
+    }
+}